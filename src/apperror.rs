@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::num::ParseIntError;
+
+// One error type for the whole crate.
+// Display is the short message shown to the user, Debug adds the full cause chain for error.log:
+//     log::error!("{:?}", e)  ->  "Failed to open notes.txt\n  caused by: Permission denied (os error 13)"
+pub enum AppError {
+    Io {
+        action: &'static str,
+        target: String,
+        source: io::Error,
+    },
+    Parse {
+        input: String,
+        source: ParseIntError,
+    },
+    // A file that could be read but is not valid in its format, e.g. broken JSON
+    Syntax {
+        target: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // A path that leaves the root directory set with fileio::set_root
+    OutsideRoot {
+        path: String,
+        root: String,
+        through_link: bool,
+    },
+    // Something typed or read that can not be used as asked: a query, a pipeline, text the chosen
+    // encoding can not hold. `what` names it, e.g. "query".
+    Invalid {
+        what: &'static str,
+        message: String,
+    },
+    // Something other than a file that was looked up and is not there, e.g. a trash entry.
+    // `what` starts the message, e.g. "Trash entry".
+    NotFound {
+        what: &'static str,
+        name: String,
+    },
+    // An encrypted file that can not be opened: a wrong passphrase, tampering or a damaged header
+    Crypto {
+        target: String,
+        message: &'static str,
+    },
+    Calculator(CalcError),
+    List(ListError),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CalcError {
+    DivisionByZero,
+    Overflow,
+    NegativeExponent(i32),
+    NegativeFactorial(i32),
+    UnknownOperation(char),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ListError {
+    IndexOutOfBounds(usize),
+    ValueNotFound(String),
+}
+
+impl AppError {
+    // `action` reads as "Failed to <action> <target>", e.g. AppError::io("open", path, e)
+    pub fn io(action: &'static str, target: impl Into<String>, source: io::Error) -> Self {
+        AppError::Io {
+            action,
+            target: target.into(),
+            source,
+        }
+    }
+
+    // `line` and `column` start at 1
+    pub fn syntax(target: impl Into<String>, line: usize, column: usize, message: impl Into<String>) -> Self {
+        AppError::Syntax {
+            target: target.into(),
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid(what: &'static str, message: impl Into<String>) -> Self {
+        AppError::Invalid { what, message: message.into() }
+    }
+
+    pub fn not_found(what: &'static str, name: impl Into<String>) -> Self {
+        AppError::NotFound { what, name: name.into() }
+    }
+
+    pub fn crypto(target: impl Into<String>, message: &'static str) -> Self {
+        AppError::Crypto { target: target.into(), message }
+    }
+
+    pub fn parse(input: impl Into<String>, source: ParseIntError) -> Self {
+        AppError::Parse {
+            input: input.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // The standard streams are not files, "File not found: stdin" would only confuse
+            AppError::Io { target, source, .. }
+                if source.kind() == io::ErrorKind::NotFound && !matches!(target.as_str(), "stdin" | "stdout" | "stderr") =>
+            {
+                write!(f, "File not found: {}", target)
+            }
+            AppError::Io { action, target, .. } => write!(f, "Failed to {} {}", action, target),
+            AppError::Parse { input, .. } => write!(f, "'{}' is not a valid number", input),
+            AppError::Syntax { target, line, column, message } => {
+                write!(f, "{}:{}:{}: {}", target, line, column, message)
+            }
+            AppError::OutsideRoot { path, root, through_link: false } => {
+                write!(f, "{} is outside the allowed directory {}", path, root)
+            }
+            AppError::OutsideRoot { path, root, through_link: true } => {
+                write!(f, "{} leads outside the allowed directory {} through a symbolic link", path, root)
+            }
+            AppError::Invalid { what, message } => write!(f, "Invalid {}: {}", what, message),
+            AppError::NotFound { what, name } => write!(f, "{} not found: {}", what, name),
+            AppError::Crypto { target, message } => write!(f, "{}: {}", target, message),
+            AppError::Calculator(e) => write!(f, "{}", e),
+            AppError::List(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl fmt::Debug for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)?;
+        let mut cause = self.source();
+        while let Some(e) = cause {
+            write!(f, "\n  caused by: {}", e)?;
+            cause = e.source();
+        }
+        Ok(())
+    }
+}
+
+impl Error for AppError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AppError::Io { source, .. } => Some(source),
+            AppError::Parse { source, .. } => Some(source),
+            AppError::Syntax { .. } | AppError::OutsideRoot { .. } | AppError::Invalid { .. } => None,
+            AppError::NotFound { .. } | AppError::Crypto { .. } => None,
+            AppError::Calculator(_) | AppError::List(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::DivisionByZero => write!(f, "Cannot divide by zero"),
+            CalcError::Overflow => write!(f, "Result is too large"),
+            CalcError::NegativeExponent(b) => write!(f, "Exponent {} must not be negative", b),
+            CalcError::NegativeFactorial(a) => write!(f, "Factorial of {} is undefined", a),
+            CalcError::UnknownOperation(op) => write!(f, "Invalid operation '{}'", op),
+        }
+    }
+}
+
+impl fmt::Display for ListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListError::IndexOutOfBounds(index) => write!(f, "Index {} is out of bounds", index),
+            ListError::ValueNotFound(value) => write!(f, "Value {} not found in list", value),
+        }
+    }
+}
+
+impl From<CalcError> for AppError {
+    fn from(e: CalcError) -> Self {
+        AppError::Calculator(e)
+    }
+}
+
+impl From<ListError> for AppError {
+    fn from(e: ListError) -> Self {
+        AppError::List(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_adds_the_cause_chain() {
+        let e = AppError::io("open", "notes.txt", io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(e.to_string(), "Failed to open notes.txt");
+        assert_eq!(format!("{:?}", e), "Failed to open notes.txt\n  caused by: permission denied");
+
+        // An io::Error wrapping another error shows that error's causes too
+        let parse = AppError::parse("x1", "x1".parse::<i32>().unwrap_err());
+        let e = AppError::io("read", "numbers.txt", io::Error::other(parse));
+        assert_eq!(
+            format!("{:?}", e),
+            "Failed to read numbers.txt\n  caused by: 'x1' is not a valid number\n  caused by: invalid digit found in string"
+        );
+
+        let missing = AppError::io("open", "gone.txt", io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(missing.to_string(), "File not found: gone.txt");
+        let stdin = AppError::io("read from", "stdin", io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(stdin.to_string(), "Failed to read from stdin");
+    }
+
+    #[test]
+    fn errors_without_a_cause_debug_like_they_display() {
+        let errors = [
+            AppError::from(CalcError::DivisionByZero),
+            AppError::from(ListError::IndexOutOfBounds(3)),
+            AppError::invalid("query", "unknown column 'age'"),
+            AppError::not_found("Trash entry", "1234"),
+        ];
+        for e in errors {
+            assert!(e.source().is_none());
+            assert_eq!(format!("{:?}", e), e.to_string());
+        }
+        assert_eq!(AppError::from(CalcError::NegativeFactorial(-2)).to_string(), "Factorial of -2 is undefined");
+    }
+}
@@ -1,37 +1,92 @@
-pub fn add (a: i32, b: i32) -> i32 {
-    a + b
+use crate::apperror::{AppError, CalcError};
+
+pub fn add (a: i32, b: i32) -> Result<i32, AppError> {
+    a.checked_add(b).ok_or(CalcError::Overflow.into())
 }
 
-pub fn subtract (a: i32, b: i32) -> i32 {
-    a - b
+pub fn subtract (a: i32, b: i32) -> Result<i32, AppError> {
+    a.checked_sub(b).ok_or(CalcError::Overflow.into())
 }
 
-pub fn multiply (a: i32, b: i32) -> i32 {
-    a * b
+pub fn multiply (a: i32, b: i32) -> Result<i32, AppError> {
+    a.checked_mul(b).ok_or(CalcError::Overflow.into())
 }
 
-pub fn divide (a: i32, b: i32) -> f32 {
-    a as f32/ b as f32
+pub fn divide (a: i32, b: i32) -> Result<f32, AppError> {
+    if b == 0 {
+        return Err(CalcError::DivisionByZero.into());
+    }
+    Ok(a as f32/ b as f32)
 }
 
-pub fn modulo (a: i32, b: i32) -> i32 {
-    a % b
+pub fn modulo (a: i32, b: i32) -> Result<i32, AppError> {
+    if b == 0 {
+        return Err(CalcError::DivisionByZero.into());
+    }
+    a.checked_rem(b).ok_or(CalcError::Overflow.into())
 }
 
-pub fn power (a: i32, b: i32) -> i32 {
-    a.pow(b as u32)
+pub fn power (a: i32, b: i32) -> Result<i32, AppError> {
+    if b < 0 {
+        return Err(CalcError::NegativeExponent(b).into());
+    }
+    a.checked_pow(b as u32).ok_or(CalcError::Overflow.into())
 }
 
-pub fn factorial (a: i32) -> i32 {
-    if a == 0 {
-        return 1;
+pub fn factorial (a: i32) -> Result<i32, AppError> {
+    if a < 0 {
+        Err(CalcError::NegativeFactorial(a).into())
+    }
+    else if a == 0 {
+        Ok(1)
     }
     else {
         let mut a_factorial = a;
         for i in 1..a{
-            a_factorial *= i;
+            a_factorial = a_factorial.checked_mul(i).ok_or(CalcError::Overflow)?;
         }
         //return a * factorial(a - 1);
-        return a_factorial
+        Ok(a_factorial)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc_error(result: Result<impl std::fmt::Debug, AppError>) -> CalcError {
+        match result {
+            Err(AppError::Calculator(e)) => e,
+            other => panic!("expected a calculator error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn calculates() {
+        assert_eq!(add(2, 3).unwrap(), 5);
+        assert_eq!(subtract(2, 3).unwrap(), -1);
+        assert_eq!(multiply(-4, 3).unwrap(), -12);
+        assert_eq!(divide(7, 2).unwrap(), 3.5);
+        assert_eq!(modulo(7, 3).unwrap(), 1);
+        assert_eq!(power(2, 10).unwrap(), 1024);
+        assert_eq!(power(5, 0).unwrap(), 1);
+        assert_eq!(factorial(0).unwrap(), 1);
+        assert_eq!(factorial(5).unwrap(), 120);
+        assert_eq!(factorial(12).unwrap(), 479001600);
+    }
+
+    #[test]
+    fn reports_division_by_zero_and_overflow() {
+        assert_eq!(calc_error(divide(1, 0)), CalcError::DivisionByZero);
+        assert_eq!(calc_error(modulo(1, 0)), CalcError::DivisionByZero);
+        assert_eq!(calc_error(add(i32::MAX, 1)), CalcError::Overflow);
+        assert_eq!(calc_error(subtract(i32::MIN, 1)), CalcError::Overflow);
+        assert_eq!(calc_error(multiply(i32::MAX, 2)), CalcError::Overflow);
+        assert_eq!(calc_error(modulo(i32::MIN, -1)), CalcError::Overflow);
+        assert_eq!(calc_error(power(2, 31)), CalcError::Overflow);
+        assert_eq!(calc_error(factorial(13)), CalcError::Overflow);
+        assert_eq!(calc_error(power(2, -1)), CalcError::NegativeExponent(-1));
+        assert_eq!(calc_error(factorial(-3)), CalcError::NegativeFactorial(-3));
+        assert_eq!(divide(1, 0).unwrap_err().to_string(), "Cannot divide by zero");
+    }
+}
@@ -0,0 +1,837 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+use log::error;
+use crate::apperror::AppError;
+use crate::fileio::{self, Algorithm, DiffOptions, Encoding, EntryKind, Format, LineEnding, Query, WatchMode, WatchOptions, HexDumpOptions, InvalidUtf8, LineSelection, Pipeline, SearchOptions, Trash, WriteMode};
+
+// Exit codes: 0 success, 1 the operation failed, 2 the command line was wrong
+const EXIT_FAILURE: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+
+const USAGE: &str = "\
+Usage: underpassrust [--root <dir>] [command]
+
+Without a command the interactive menu is started.
+
+--root <dir> limits every file path to <dir>: relative paths start there, and paths
+that lead outside it, through .. or a symbolic link, are refused. The UNDERPASS_ROOT
+environment variable does the same and also applies to the interactive menu.
+
+Commands:
+  file cat <path> [--head <n> | --tail <n> | --lines <a-b>] [--invalid lossy|hex]
+                                     Print a file, or only the selected lines, to stdout.
+                                     --invalid sets how bytes that are not UTF-8 are shown
+  file show <path> [--format csv|tsv|json|toml|text]
+                                     Print a CSV/TSV file as an aligned table, JSON
+                                     pretty-printed or TOML normalised. The format is
+                                     detected from the extension or contents by default,
+                                     syntax errors are reported with line and column
+  file query <path> <query> [--out <path>] [--mode <mode>]
+                                     Run a query over a CSV file and print the result as a
+                                     table, or write it as CSV to --out (a new file unless
+                                     --mode says otherwise). The query reads like
+                                     select <cols|*|count|sum(col)|avg(col)>
+                                       [where <col> <op> <value> [and ...]] [group by <col>]
+                                       [order by <col> [asc|desc]] [limit <n>]
+                                     with op one of = != < <= > >= ~ (contains)
+  file write <path> [--mode <mode>] [--atomic] [--backup] [--encoding <enc>] [--eol lf|crlf]
+             [--encrypt] [--text <text> | --from <file>]
+                                     Write a file, reads stdin when no text or file is given.
+                                     <mode> is create (new files only), truncate (existing
+                                     files only) or overwrite (the default).
+                                     --atomic writes a temp file and renames it into place,
+                                     --backup also keeps the old contents in <path>.bak.
+                                     <enc> is utf-8 (the default), utf-8-bom, utf-16le,
+                                     utf-16be or latin-1, --eol converts line endings.
+                                     --from files may be in any of those encodings.
+                                     --encrypt protects the file with a passphrase (always
+                                     atomic), see decrypt
+  file decrypt <path> [--out <path>] Print an encrypted file, or write it to a new file.
+                                     The passphrase is asked on the terminal, or taken from
+                                     UNDERPASS_PASSPHRASE. A wrong one and a file changed
+                                     since it was written are both reported and exit with 1
+  file encoding <path>               Show the detected encoding and line endings
+  file convert <path> [--encoding <enc>] [--eol lf|crlf] [--out <path> [--force]]
+                                     Re-encode a file in place, or into --out, which must
+                                     not exist unless --force is given
+  file grep <pattern> <path> [--regex] [-i] [-w] [-C <n>]
+                                     Search a file or directory tree. -i ignores case, -w
+                                     matches whole words, -C shows n lines of context.
+                                     Exits with 1 when nothing matched
+  file checksum <path>... [--algo crc32|md5|sha1|sha256]
+                                     Print the digest and path of each file, sha256 by
+                                     default. The output can be saved as a manifest
+                                     for verify
+  file verify <manifest> [--algo <name>]
+                                     Check the files listed in a manifest, relative paths
+                                     are taken from its directory. Exits with 1 on any
+                                     mismatch or unreadable file
+  file diff <old> <new> [-U <n>] [--side-by-side] [--width <n>]
+                                     Compare two files line by line. Unified output with n
+                                     lines of context (3 by default), or two columns of
+                                     --width characters each. Exits with 1 when they differ
+  file hexdump <path> [--width <n>] [--offset <n>] [--length <n>]
+                                     Show offset, hex bytes and ASCII, numbers may be 0x hex
+  file append <path> [--eol lf|crlf] [--text <text> | --from <file>]
+                                     Append to a file, reads stdin when no text or file is given.
+                                     The text is written in the file's encoding and line
+                                     endings, --eol overrides the line endings
+  file cp <from> <to> [--force]      Copy a file or directory. Onto an existing directory it
+                                     is copied inside it, existing files are only replaced
+                                     with --force
+  file mv <from> <to> [--force]      Move or rename a file or directory, same rules as cp
+  file rm <path>...                  Move files or directories to the trash, a
+                                     .underpass-trash directory in the working directory
+  file trash                         List the trash, with the ids restore takes
+  file restore <id> [--force]        Put a trashed entry back where it was, --force replaces
+                                     a file that has been created there since
+  file empty-trash [--yes]           Delete everything in the trash for good. Asks first on a
+                                     terminal, otherwise --yes is required
+  file mkdir <path>                  Create a directory and any missing parents
+  file tar list <archive>            List the entries of a tar archive
+  file tar create <archive> <path>... [--force]
+                                     Archive files and directories, --force replaces an
+                                     existing archive
+  file tar extract <archive> [<dir>] [--force]
+                                     Unpack into <dir> (the working directory by default).
+                                     Entries with absolute or .. paths are refused, links
+                                     are skipped, --force replaces existing files
+  file stats <path>... [--top <n>]   Count lines, words, characters and bytes, and show the
+                                     longest and average line length and the n most frequent
+                                     words (10 by default). Several files are compared in a
+                                     table with a total
+  file transform <path> <pipeline> [--out <path> [--force] | --in-place]
+                                     Run the lines of a file through steps separated by |,
+                                     e.g. 'sort | uniq | upper'. Steps are sort [-r] [-n],
+                                     uniq, reverse, upper, lower, trim, number and
+                                     replace /regex/replacement/[i]. Prints the result, or
+                                     writes it back into the file or to --out, which must
+                                     not exist unless --force is given
+  file watch <path> [--tail] [--interval <ms>]
+                                     Print the file again whenever its size or modification
+                                     time changes, or with --tail only the appended lines.
+                                     Checks every 500 ms by default, Ctrl+C stops
+  help                               Show this message";
+
+// Runs a non-interactive command, `args` excludes the program name
+pub fn run(args: &[String]) -> ExitCode {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["help" | "--help" | "-h"] => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        ["--root", dir, rest @ ..] => match fileio::set_root(dir) {
+            Ok(()) => run(&rest.iter().map(|arg| arg.to_string()).collect::<Vec<_>>()),
+            Err(e) => {
+                error!("{:?}", e);
+                ExitCode::from(EXIT_FAILURE)
+            }
+        },
+        ["file", rest @ ..] => file_command(rest),
+        _ => usage_error("unknown command"),
+    }
+}
+
+fn file_command(args: &[&str]) -> ExitCode {
+    let result = match args {
+        ["cat", path] => fileio::resolve(path)
+            .and_then(|resolved| File::open(resolved).map_err(|e| AppError::io("open", *path, e)))
+            .and_then(|mut file| {
+                // Without options the bytes are copied as they are, binary files included
+                let mut stdout = io::stdout().lock();
+                io::copy(&mut file, &mut stdout)
+                    .and_then(|_| stdout.flush())
+                    .or_else(ignore_broken_pipe)
+                    .map_err(|e| AppError::io("copy to stdout", *path, e))
+            }),
+        ["cat", path, rest @ ..] => {
+            let (selection, invalid) = match parse_cat_args(rest) {
+                Ok(options) => options,
+                Err(message) => return usage_error(message),
+            };
+            print_lines(|print| fileio::for_each_line(path, selection, invalid, |_, line| print(line)))
+        }
+        ["grep", rest @ ..] => {
+            let (options, pattern, path) = match parse_grep_args(rest) {
+                Ok(parsed) => parsed,
+                Err(message) => return usage_error(message),
+            };
+            let matcher = match fileio::build_matcher(pattern, options) {
+                Ok(matcher) => matcher,
+                Err(e) => return invalid_input(AppError::invalid("pattern", e.to_string())),
+            };
+            let mut count = 0;
+            let result = print_lines(|print| {
+                fileio::search(path, &matcher, options.context, print).map(|matches| count = matches)
+            });
+            // Like grep, finding nothing is reported through the exit code
+            if result.is_ok() && count == 0 {
+                return ExitCode::from(EXIT_FAILURE);
+            }
+            result
+        }
+        ["checksum", rest @ ..] => {
+            let (algorithm, paths) = match parse_checksum_args(rest) {
+                Ok(parsed) => parsed,
+                Err(message) => return usage_error(message),
+            };
+            let paths = match paths.as_slice() {
+                [] => return usage_error("checksum needs at least one path"),
+                paths => paths,
+            };
+            let algorithm = algorithm.unwrap_or(Algorithm::Sha256);
+            print_lines(|print| {
+                for path in paths {
+                    let digest = fileio::checksum(path, algorithm)?;
+                    if !print(&format!("{}  {}", digest, path)) {
+                        break;
+                    }
+                }
+                Ok(())
+            })
+        }
+        ["verify", rest @ ..] => {
+            let (algorithm, paths) = match parse_checksum_args(rest) {
+                Ok(parsed) => parsed,
+                Err(message) => return usage_error(message),
+            };
+            let manifest = match paths.as_slice() {
+                [manifest] => *manifest,
+                _ => return usage_error("verify needs exactly one manifest"),
+            };
+            let mut all_ok = true;
+            let result = print_lines(|print| {
+                fileio::verify(manifest, algorithm, print).map(|summary| {
+                    all_ok = summary.failed == 0 && summary.unreadable == 0;
+                })
+            });
+            if result.is_ok() && !all_ok {
+                return ExitCode::from(EXIT_FAILURE);
+            }
+            result
+        }
+        ["diff", old, new, rest @ ..] => {
+            let options = match parse_diff_args(rest) {
+                Ok(options) => options,
+                Err(message) => return usage_error(message),
+            };
+            let mut differ = false;
+            let result = print_lines(|print| fileio::diff(old, new, options, print).map(|d| differ = d));
+            // Like diff, a difference is reported through the exit code
+            if result.is_ok() && differ {
+                return ExitCode::from(EXIT_FAILURE);
+            }
+            result
+        }
+        ["hexdump", path, rest @ ..] => {
+            let options = match parse_hexdump_args(rest) {
+                Ok(options) => options,
+                Err(message) => return usage_error(message),
+            };
+            print_lines(|print| fileio::hexdump(path, options, print))
+        }
+        ["show", path, rest @ ..] => {
+            let format = match rest {
+                [] => fileio::detect_file_format(path),
+                ["--format", name] => match Format::parse(name) {
+                    Some(format) => Ok(format),
+                    None => return usage_error("--format must be csv, tsv, json, toml or text"),
+                },
+                _ => return usage_error("unexpected argument"),
+            };
+            format.and_then(|format| print_lines(|print| fileio::render(path, format, print)))
+        }
+        ["query", path, text, rest @ ..] => {
+            let (out, mode) = match rest {
+                [] => (None, WriteMode::Create),
+                ["--out", out] => (Some(*out), WriteMode::Create),
+                ["--out", out, "--mode", name] | ["--mode", name, "--out", out] => match WriteMode::parse(name) {
+                    Some(mode) => (Some(*out), mode),
+                    None => return usage_error("--mode must be create, truncate or overwrite"),
+                },
+                _ => return usage_error("unexpected argument"),
+            };
+            let query = match Query::parse(text) {
+                Ok(query) => query,
+                Err(e) => return invalid_input(e),
+            };
+            let result = fileio::read_csv(path).and_then(|records| query.run(&records));
+            result.and_then(|result| match out {
+                Some(out) => {
+                    let csv = fileio::format_csv(&result, fileio::delimiter_for(out));
+                    fileio::write(out, csv.as_bytes(), mode)
+                }
+                None => print_lines(|print| {
+                    for line in fileio::format_table(&result) {
+                        if !print(&line) {
+                            break;
+                        }
+                    }
+                    Ok(())
+                }),
+            })
+        }
+        ["encoding", path] => fileio::detect_file_encoding(path).and_then(|encoding| {
+            let encoding = match encoding {
+                Some(encoding) => encoding,
+                None => {
+                    println!("binary");
+                    return Ok(());
+                }
+            };
+            let (text, _) = fileio::read_decoded(path)?;
+            match LineEnding::detect(&text) {
+                Some(ending) => println!("{}, {} line endings", encoding.name(), ending.name()),
+                None => println!("{}, no line breaks", encoding.name()),
+            }
+            Ok(())
+        }),
+        ["convert", path, rest @ ..] => {
+            let mut encoding = None;
+            let mut eol = None;
+            let mut out = None;
+            let mut force = false;
+            let mut args = rest.iter();
+            while let Some(arg) = args.next() {
+                if matches!(*arg, "--force" | "-f") {
+                    force = true;
+                    continue;
+                }
+                let value = match args.next() {
+                    Some(value) => *value,
+                    None => return usage_error("option needs a value"),
+                };
+                match *arg {
+                    "--encoding" => match Encoding::parse(value) {
+                        Some(chosen) => encoding = Some(chosen),
+                        None => return usage_error("--encoding must be utf-8, utf-8-bom, utf-16le, utf-16be or latin-1"),
+                    },
+                    "--eol" => match LineEnding::parse(value) {
+                        Some(chosen) => eol = Some(chosen),
+                        None => return usage_error("--eol must be lf or crlf"),
+                    },
+                    "--out" => out = Some(value),
+                    _ => return usage_error("unexpected argument"),
+                }
+            }
+            // In place the file is replaced on purpose, another one only with --force
+            let (out, mode) = match out {
+                None => (*path, WriteMode::Truncate),
+                Some(out) if force => (out, WriteMode::Overwrite),
+                Some(out) => (out, WriteMode::Create),
+            };
+            let (text, detected) = match fileio::read_decoded(path) {
+                Ok((text, Some(detected))) => (text, detected),
+                // Re-encoding would rewrite its bytes, and --eol every 0x0A and 0x0D in it
+                Ok((_, None)) => {
+                    let e = io::Error::new(io::ErrorKind::InvalidData, "the file looks binary, not text");
+                    error!("{:?}", AppError::io("convert", *path, e));
+                    return ExitCode::from(EXIT_FAILURE);
+                }
+                Err(e) => {
+                    error!("{:?}", e);
+                    return ExitCode::from(EXIT_FAILURE);
+                }
+            };
+            let text = match eol {
+                Some(ending) => fileio::convert_line_endings(&text, ending),
+                None => text,
+            };
+            fileio::encode(&text, encoding.unwrap_or(detected))
+                .and_then(|bytes| fileio::write_atomic(out, &bytes, mode, false))
+        }
+        ["watch", path, rest @ ..] => {
+            let mut options = WatchOptions::default();
+            let mut args = rest.iter();
+            while let Some(arg) = args.next() {
+                match *arg {
+                    "--tail" | "-f" => options.mode = WatchMode::Tail,
+                    "--interval" => match args.next().and_then(|ms| ms.parse().ok()).filter(|ms| *ms > 0) {
+                        Some(ms) => options.interval = Duration::from_millis(ms),
+                        None => return usage_error("--interval needs a number of milliseconds"),
+                    },
+                    _ => return usage_error("unexpected argument"),
+                }
+            }
+            // Runs until interrupted or stdout goes away
+            print_lines(|print| fileio::watch(path, options, print, || false))
+        }
+        ["cp", from, to, rest @ ..] | ["mv", from, to, rest @ ..] => {
+            let overwrite = match rest {
+                [] => false,
+                ["--force" | "-f"] => true,
+                _ => return usage_error("unexpected argument"),
+            };
+            if args[0] == "mv" {
+                fileio::move_path(from, to, overwrite)
+            } else {
+                fileio::copy(from, to, overwrite).map(|_| ())
+            }
+        }
+        ["rm", paths @ ..] if !paths.is_empty() => {
+            let trash = Trash::local();
+            // Keeps going after a failure like rm does, the exit code still reports it
+            let mut failed = false;
+            for path in paths {
+                if let Err(e) = trash.put(path) {
+                    error!("{:?}", e);
+                    failed = true;
+                }
+            }
+            if failed {
+                return ExitCode::from(EXIT_FAILURE);
+            }
+            Ok(())
+        }
+        ["trash"] => Trash::local().list().and_then(|entries| {
+            print_lines(|print| {
+                for entry in &entries {
+                    let kind = if entry.is_dir { "/" } else { "" };
+                    let line = format!("{}\t{} UTC\t{}{}", entry.id, fileio::format_time(entry.deleted), entry.original, kind);
+                    if !print(&line) {
+                        break;
+                    }
+                }
+                Ok(())
+            })
+        }),
+        ["restore", id, rest @ ..] => {
+            let overwrite = match rest {
+                [] => false,
+                ["--force" | "-f"] => true,
+                _ => return usage_error("unexpected argument"),
+            };
+            Trash::local().restore(id, overwrite).map(|path| println!("{}", path))
+        }
+        ["empty-trash", rest @ ..] => {
+            let confirmed = match rest {
+                ["--yes" | "-y"] => Ok(true),
+                [] if io::stdin().is_terminal() => Trash::local().list().map(|entries| {
+                    eprint!("Delete the {} entries in the trash for good? (y/n): ", entries.len());
+                    let mut answer = String::new();
+                    io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "yes" | "Y")
+                }),
+                // Nobody to ask, a script has to say so
+                [] => return usage_error("empty-trash deletes for good, pass --yes to confirm"),
+                _ => return usage_error("unexpected argument"),
+            };
+            confirmed.and_then(|confirmed| {
+                if confirmed {
+                    Trash::local().empty().map(|count| println!("Deleted {} entries", count))
+                } else {
+                    println!("Nothing deleted.");
+                    Ok(())
+                }
+            })
+        }
+        ["mkdir", path] => fileio::make_dir(path),
+        ["transform", path, text, rest @ ..] => {
+            let out = match rest {
+                [] => None,
+                ["--out", out] => Some((*out, WriteMode::Create)),
+                ["--out", out, "--force" | "-f"] | ["--force" | "-f", "--out", out] => Some((*out, WriteMode::Overwrite)),
+                ["--in-place" | "-i"] => Some((*path, WriteMode::Truncate)),
+                _ => return usage_error("unexpected argument"),
+            };
+            let pipeline = match Pipeline::parse(text) {
+                Ok(pipeline) => pipeline,
+                Err(e) => return invalid_input(e),
+            };
+            fileio::transform_file(path, &pipeline).and_then(|(result, encoding)| match out {
+                Some((out, mode)) => {
+                    fileio::encode(&result, encoding).and_then(|bytes| fileio::write_atomic(out, &bytes, mode, false))
+                }
+                None => {
+                    let mut stdout = io::stdout().lock();
+                    stdout
+                        .write_all(result.as_bytes())
+                        .and_then(|_| stdout.flush())
+                        .or_else(ignore_broken_pipe)
+                        .map_err(|e| AppError::io("write to", "stdout", e))
+                }
+            })
+        }
+        ["stats", rest @ ..] => {
+            let mut top = 10;
+            let mut paths = Vec::new();
+            let mut args = rest.iter();
+            while let Some(arg) = args.next() {
+                match *arg {
+                    "--top" => match args.next().and_then(|n| n.parse().ok()) {
+                        Some(n) => top = n,
+                        None => return usage_error("--top needs a number"),
+                    },
+                    path => paths.push(path),
+                }
+            }
+            if paths.is_empty() {
+                return usage_error("stats needs at least one path");
+            }
+            let mut files = Vec::new();
+            for path in paths {
+                match fileio::text_stats(path) {
+                    Ok(stats) => files.push((path, stats)),
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return ExitCode::from(EXIT_FAILURE);
+                    }
+                }
+            }
+            print_lines(|print| {
+                for line in fileio::stats_report(&files, top) {
+                    if !print(&line) {
+                        break;
+                    }
+                }
+                Ok(())
+            })
+        }
+        ["tar", "list", archive] => print_lines(|print| {
+            fileio::list_tar(archive, |entry| {
+                let kind = match entry.kind {
+                    EntryKind::Dir => 'd',
+                    EntryKind::Link => 'l',
+                    EntryKind::File => '-',
+                    EntryKind::Other => '?',
+                };
+                let link = if entry.link.is_empty() { String::new() } else { format!(" -> {}", entry.link) };
+                print(&format!("{} {:04o} {:>10} {} {}{}", kind, entry.mode, entry.size, fileio::format_time(entry.modified), entry.path, link))
+            })
+            .map(|_| ())
+        }),
+        ["tar", "create", archive, rest @ ..] => {
+            let force = rest.contains(&"--force") || rest.contains(&"-f");
+            let paths: Vec<&str> = rest.iter().copied().filter(|arg| *arg != "--force" && *arg != "-f").collect();
+            if paths.is_empty() {
+                return usage_error("tar create needs at least one path");
+            }
+            let mode = if force { WriteMode::Overwrite } else { WriteMode::Create };
+            fileio::create_tar(archive, &paths, mode, |_| {}).map(|_| ())
+        }
+        ["tar", "extract", archive, rest @ ..] => {
+            let force = rest.contains(&"--force") || rest.contains(&"-f");
+            let dest = match rest.iter().filter(|arg| **arg != "--force" && **arg != "-f").collect::<Vec<_>>().as_slice() {
+                [] => ".",
+                [dest] => **dest,
+                _ => return usage_error("tar extract takes one destination directory"),
+            };
+            fileio::extract_tar(archive, dest, force, |entry| {
+                if entry.kind != EntryKind::File && entry.kind != EntryKind::Dir {
+                    eprintln!("underpassrust: skipped {}, only files and directories are extracted", entry.path);
+                }
+            })
+            .map(|_| ())
+        }
+        ["write", path, rest @ ..] => {
+            let options = match WriteArgs::parse(rest, true) {
+                Ok(options) => options,
+                Err(message) => return usage_error(message),
+            };
+            options.message().and_then(|text| options.encode(&text)).and_then(|bytes| {
+                if options.encrypt {
+                    passphrase(true).and_then(|passphrase| {
+                        fileio::write_encrypted(path, &bytes, &passphrase, options.mode, options.backup)
+                    })
+                } else if options.atomic || options.backup {
+                    fileio::write_atomic(path, &bytes, options.mode, options.backup)
+                } else {
+                    fileio::write(path, &bytes, options.mode)
+                }
+            })
+        }
+        ["decrypt", path, rest @ ..] => {
+            let out = match rest {
+                [] => None,
+                ["--out", out] => Some(*out),
+                _ => return usage_error("decrypt only takes --out <path>"),
+            };
+            let plaintext = passphrase(false).and_then(|passphrase| fileio::read_encrypted(path, &passphrase));
+            plaintext.and_then(|plaintext| match out {
+                Some(out) => fileio::write(out, &plaintext, WriteMode::Create),
+                None => {
+                    let mut stdout = io::stdout().lock();
+                    stdout
+                        .write_all(&plaintext)
+                        .and_then(|_| stdout.flush())
+                        .or_else(ignore_broken_pipe)
+                        .map_err(|e| AppError::io("write to", "stdout", e))
+                }
+            })
+        }
+        ["append", path, rest @ ..] => {
+            let options = match WriteArgs::parse(rest, false) {
+                Ok(options) => options,
+                Err(message) => return usage_error(message),
+            };
+            options.message().and_then(|text| fileio::append_text(path, &text, options.eol))
+        }
+        _ => return usage_error("unknown or incomplete file command"),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{:?}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn parse_cat_args(args: &[&str]) -> Result<(LineSelection, InvalidUtf8), &'static str> {
+    let mut selection = LineSelection::All;
+    let mut invalid = InvalidUtf8::Lossy;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or("option needs a value")?;
+        match *arg {
+            "--head" => selection = LineSelection::Head(value.parse().map_err(|_| "--head needs a number")?),
+            "--tail" => selection = LineSelection::Tail(value.parse().map_err(|_| "--tail needs a number")?),
+            "--lines" => {
+                selection = match LineSelection::parse(value) {
+                    Some(range @ LineSelection::Range(..)) => range,
+                    _ => return Err("--lines needs a range like 10-20"),
+                }
+            }
+            "--invalid" => {
+                invalid = match *value {
+                    "lossy" => InvalidUtf8::Lossy,
+                    "hex" => InvalidUtf8::Hex,
+                    _ => return Err("--invalid must be lossy or hex"),
+                }
+            }
+            _ => return Err("unexpected argument"),
+        }
+    }
+    Ok((selection, invalid))
+}
+
+// Options may come before or after the pattern and path
+fn parse_grep_args<'a>(args: &[&'a str]) -> Result<(SearchOptions, &'a str, &'a str), &'static str> {
+    let mut options = SearchOptions::default();
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--regex" => options.regex = true,
+            "-i" | "--ignore-case" => options.ignore_case = true,
+            "-w" | "--word" => options.whole_word = true,
+            "-C" | "--context" => {
+                let value = args.next().ok_or("-C needs a number")?;
+                options.context = value.parse().map_err(|_| "-C needs a number")?;
+            }
+            arg if arg.starts_with('-') && arg.len() > 1 => return Err("unexpected option"),
+            arg => positional.push(arg),
+        }
+    }
+    match positional.as_slice() {
+        [pattern, path] => Ok((options, pattern, path)),
+        _ => Err("grep needs a pattern and a path"),
+    }
+}
+
+// Without --algo, checksum uses sha256 and verify guesses from each digest's length
+fn parse_checksum_args<'a>(args: &[&'a str]) -> Result<(Option<Algorithm>, Vec<&'a str>), &'static str> {
+    let mut algorithm = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--algo" => {
+                let name = args.next().ok_or("--algo needs a name")?;
+                algorithm = Some(Algorithm::parse(name).ok_or("--algo must be crc32, md5, sha1 or sha256")?);
+            }
+            arg if arg.starts_with('-') && arg.len() > 1 => return Err("unexpected option"),
+            arg => paths.push(arg),
+        }
+    }
+    Ok((algorithm, paths))
+}
+
+fn parse_diff_args(args: &[&str]) -> Result<DiffOptions, &'static str> {
+    let mut options = DiffOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-U" | "--context" => {
+                let value = args.next().ok_or("-U needs a number")?;
+                options.context = value.parse().map_err(|_| "-U needs a number")?;
+            }
+            "--side-by-side" | "-y" => options.side_by_side = true,
+            "--width" => {
+                let value = args.next().ok_or("--width needs a number")?;
+                options.width = value.parse().map_err(|_| "--width needs a number")?;
+            }
+            _ => return Err("unexpected argument"),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_hexdump_args(args: &[&str]) -> Result<HexDumpOptions, &'static str> {
+    let mut options = HexDumpOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or("option needs a value")?;
+        let number = fileio::parse_number(value).ok_or("expected a number")?;
+        match *arg {
+            "--width" if (1..=64).contains(&number) => options.width = number as usize,
+            "--width" => return Err("--width must be between 1 and 64"),
+            "--offset" => options.offset = number,
+            "--length" => options.length = Some(number),
+            _ => return Err("unexpected argument"),
+        }
+    }
+    Ok(options)
+}
+
+// Hands `produce` a callback that writes one line to stdout and returns false once stdout fails,
+// so streaming commands stop early instead of panicking when piped into `head`
+fn print_lines<F>(produce: F) -> Result<(), AppError>
+where
+    F: FnOnce(&mut dyn FnMut(&str) -> bool) -> Result<(), AppError>,
+{
+    let mut stdout = io::stdout().lock();
+    let mut write_error = None;
+    produce(&mut |line| match writeln!(stdout, "{}", line) {
+        Ok(()) => true,
+        Err(e) => {
+            write_error = Some(e);
+            false
+        }
+    })?;
+    match write_error.map_or(Ok(()), Err).or_else(ignore_broken_pipe) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(AppError::io("write to", "stdout", e)),
+    }
+}
+
+// Piping into `head` closes stdout early, that is not a failure of ours
+fn ignore_broken_pipe(e: io::Error) -> io::Result<()> {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        Ok(())
+    } else {
+        Err(e)
+    }
+}
+
+// Options of `file write` and `file append`
+struct WriteArgs<'a> {
+    text: Option<&'a str>,
+    from: Option<&'a str>,
+    mode: WriteMode,
+    atomic: bool,
+    backup: bool,
+    encoding: Encoding,
+    eol: Option<LineEnding>,
+    encrypt: bool,
+}
+
+impl<'a> WriteArgs<'a> {
+    // `full` enables the write-only options, append only takes --text, --from and --eol
+    fn parse(args: &[&'a str], full: bool) -> Result<WriteArgs<'a>, &'static str> {
+        let mut options = WriteArgs {
+            text: None,
+            from: None,
+            mode: WriteMode::Overwrite,
+            atomic: false,
+            backup: false,
+            encoding: Encoding::Utf8,
+            eol: None,
+            encrypt: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "--text" => options.text = Some(args.next().ok_or("--text needs a value")?),
+                "--from" => options.from = Some(args.next().ok_or("--from needs a path")?),
+                "--mode" if full => {
+                    let name = args.next().ok_or("--mode needs a value")?;
+                    options.mode = WriteMode::parse(name)
+                        .ok_or("--mode must be create, truncate or overwrite")?;
+                }
+                "--atomic" if full => options.atomic = true,
+                "--backup" if full => options.backup = true,
+                "--encrypt" if full => options.encrypt = true,
+                "--encoding" if full => {
+                    let name = args.next().ok_or("--encoding needs a value")?;
+                    options.encoding = Encoding::parse(name)
+                        .ok_or("--encoding must be utf-8, utf-8-bom, utf-16le, utf-16be or latin-1")?;
+                }
+                "--eol" => {
+                    let name = args.next().ok_or("--eol needs a value")?;
+                    options.eol = Some(LineEnding::parse(name).ok_or("--eol must be lf or crlf")?);
+                }
+                arg => match arg.strip_prefix("--text=") {
+                    Some(text) => options.text = Some(text),
+                    None => return Err("unexpected argument"),
+                },
+            }
+        }
+        if options.text.is_some() && options.from.is_some() {
+            return Err("--text and --from can not be combined");
+        }
+        Ok(options)
+    }
+
+    // The --text value, the contents of the --from file, or all of stdin when neither was given
+    fn message(&self) -> Result<String, AppError> {
+        if let Some(text) = self.text {
+            return Ok(text.to_string());
+        }
+        if let Some(path) = self.from {
+            return fileio::read_decoded(path).map(|(text, _)| text);
+        }
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map(|_| text)
+            .map_err(|e| AppError::io("read from", "stdin", e))
+    }
+
+    // The message in the requested line endings and encoding
+    fn encode(&self, text: &str) -> Result<Vec<u8>, AppError> {
+        match self.eol {
+            Some(ending) => fileio::encode(&fileio::convert_line_endings(text, ending), self.encoding),
+            None => fileio::encode(text, self.encoding),
+        }
+    }
+}
+
+// UNDERPASS_PASSPHRASE for scripts, otherwise asked on the terminal, twice for a new one. Stdin
+// is left alone, it may be carrying the text to write.
+fn passphrase(new: bool) -> Result<String, AppError> {
+    if let Ok(passphrase) = env::var("UNDERPASS_PASSPHRASE") {
+        return if passphrase.is_empty() {
+            Err(AppError::invalid("passphrase", "UNDERPASS_PASSPHRASE is empty"))
+        } else {
+            Ok(passphrase)
+        };
+    }
+    let ask = |label: &str| {
+        rpassword::prompt_password(label)
+            .map_err(|e| AppError::io("ask for the passphrase on", "the terminal (or set UNDERPASS_PASSPHRASE)", e))
+    };
+    let passphrase = ask("Passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(AppError::invalid("passphrase", "it can not be empty"));
+    }
+    if new && ask("Repeat the passphrase: ")? != passphrase {
+        return Err(AppError::invalid("passphrase", "the two entries do not match"));
+    }
+    Ok(passphrase)
+}
+
+// A pattern, query or pipeline that can not be used is a mistake on the command line
+fn invalid_input(e: AppError) -> ExitCode {
+    error!("{:?}", e);
+    ExitCode::from(EXIT_USAGE)
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("underpassrust: {}\n\n{}", message, USAGE);
+    ExitCode::from(EXIT_USAGE)
+}
@@ -1,22 +1,195 @@
-use std::fs::{OpenOptions, File};
+mod ratelimit;
+mod redact;
+
+use std::fs::{self, OpenOptions};
 use std::io::Write;
-use std::fmt::Display;
-use std::time::SystemTime;
-
-pub fn log_error<T: Display>(error: T) {
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("error.log")
-        .expect("Failed to open error.log");
-
-    let time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Failed to get time")
-        .as_secs();
-
-    let error_message = format!("{}: {}\n", time, error);
-    file.write_all(error_message.as_bytes())
-        .expect("Failed to write to error.log");
-    print!("{}", error_message);
-}
\ No newline at end of file
+use std::backtrace::Backtrace;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Instant, SystemTime};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use ratelimit::Throttle;
+pub use redact::Redactor;
+
+const LOG_FILE: &str = "error.log";
+// Optional extra redaction rules, see Redactor::with_rules for the format
+const REDACT_RULES_FILE: &str = "redact.conf";
+
+// Every log::error!, log::warn! etc. in the crate ends up here once init() has run.
+// Records are appended to error.log, warnings and errors are also shown to the user like before.
+struct ErrorLog {
+    path: &'static str,
+    redactor: RwLock<Redactor>,
+    throttle: Mutex<Throttle>,
+    // Subcommands keep stdout for their output, so messages go to stderr there
+    to_stderr: AtomicBool,
+}
+
+impl ErrorLog {
+    fn write_record(&self, level: Level, message: &str) {
+        // Redact once, before the message reaches either the file or the terminal,
+        // and before throttling so duplicates are compared on what actually gets written
+        let message = match self.redactor.read() {
+            Ok(redactor) => redactor.redact(message),
+            Err(poisoned) => poisoned.into_inner().redact(message),
+        };
+
+        let records = match self.throttle.lock() {
+            Ok(mut throttle) => throttle.admit(level, &message, Instant::now()),
+            Err(poisoned) => poisoned.into_inner().admit(level, &message, Instant::now()),
+        };
+        for (level, message) in records {
+            self.emit(level, &message);
+        }
+    }
+
+    fn emit(&self, level: Level, message: &str) {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        let message = format!("{}: [{}] {}\n", time, level, message);
+
+        // The logger must never panic, a panic here would recurse into the panic hook
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(self.path) {
+            let _ = file.write_all(message.as_bytes());
+        }
+
+        if level <= Level::Warn {
+            if self.to_stderr.load(Ordering::Relaxed) {
+                eprint!("{}", message);
+            } else {
+                print!("{}", message);
+            }
+        }
+    }
+}
+
+impl Log for ErrorLog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.write_record(record.level(), &record.args().to_string());
+    }
+
+    // Writes out pending "repeated N times" lines and the rate limit summary
+    fn flush(&self) {
+        let records = match self.throttle.lock() {
+            Ok(mut throttle) => throttle.finish(),
+            Err(poisoned) => poisoned.into_inner().finish(),
+        };
+        for (level, message) in records {
+            self.emit(level, &message);
+        }
+    }
+}
+
+static LOGGER: ErrorLog = ErrorLog {
+    path: LOG_FILE,
+    redactor: RwLock::new(Redactor::new()),
+    throttle: Mutex::new(Throttle::new()),
+    to_stderr: AtomicBool::new(false),
+};
+
+// Replaces the redaction rules applied to every record from now on
+pub fn set_redactor(redactor: Redactor) {
+    match LOGGER.redactor.write() {
+        Ok(mut current) => *current = redactor,
+        Err(poisoned) => *poisoned.into_inner() = redactor,
+    }
+}
+
+// Call once at the start of main, before anything can log or panic
+pub fn init() {
+    set_redactor(Redactor::with_defaults());
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+
+    if let Ok(rules) = fs::read_to_string(REDACT_RULES_FILE) {
+        match Redactor::with_defaults().with_rules(&rules) {
+            Ok(redactor) => set_redactor(redactor),
+            Err(e) => log::warn!("Ignoring {}: {}", REDACT_RULES_FILE, e),
+        }
+    }
+
+    panic::set_hook(Box::new(|info| {
+        let location = match info.location() {
+            Some(location) => format!("{}:{}", location.file(), location.line()),
+            None => String::from("unknown location"),
+        };
+        // Panic payloads are either &str (panic!("literal")) or String (panic!("{}", x))
+        let payload = if let Some(message) = info.payload().downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = info.payload().downcast_ref::<String>() {
+            message.clone()
+        } else {
+            String::from("Box<dyn Any>")
+        };
+
+        log::error!("panic at {}: {}\n{}", location, payload, Backtrace::force_capture());
+        log::logger().flush();
+    }));
+}
+
+// Show warnings and errors on stderr instead of stdout
+pub fn use_stderr() {
+    LOGGER.to_stderr.store(true, Ordering::Relaxed);
+}
+
+// Call before exiting so collapsed and rate limited messages are summarised in the log
+pub fn shutdown() {
+    log::logger().flush();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_log(name: &str, redactor: Redactor) -> ErrorLog {
+        let path = std::env::temp_dir().join(format!("underpass-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        ErrorLog {
+            path: Box::leak(path.to_string_lossy().into_owned().into_boxed_str()),
+            redactor: RwLock::new(redactor),
+            throttle: Mutex::new(Throttle::new()),
+            to_stderr: AtomicBool::new(false),
+        }
+    }
+
+    #[test]
+    fn secrets_never_reach_the_log_file() {
+        let redactor = Redactor::new()
+            .with_field("password")
+            .with_home_dir("/home/alice")
+            .with_pattern(r"sk-[A-Za-z0-9]{8,}")
+            .unwrap();
+        let log = test_log("redact", redactor);
+
+        log.write_record(Level::Info, "connecting with password=hunter2");
+        log.write_record(Level::Info, "api key sk-abcdef123456 rejected");
+        log.write_record(Level::Info, "File not found: /home/alice/secret-plans.txt");
+
+        log.write_record(Level::Info, "connecting with password=hunter3");
+        log.flush();
+
+        let contents = fs::read_to_string(log.path).unwrap();
+        fs::remove_file(log.path).unwrap();
+        assert!(!contents.contains("hunter2"));
+        assert!(!contents.contains("hunter3"));
+        assert!(!contents.contains("sk-abcdef123456"));
+        assert!(!contents.contains("/home/alice"));
+        assert!(contents.contains("password=[REDACTED]"));
+        assert!(contents.contains("~/secret-plans.txt"));
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+use log::Level;
+
+// Each distinct message may burst this many times, then gets REFILL_PER_SEC more per second
+const BUCKET_CAPACITY: f64 = 10.0;
+const REFILL_PER_SEC: f64 = 1.0;
+// Past this many tracked messages, buckets that have fully refilled are forgotten
+const MAX_TRACKED: usize = 1024;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Sits between redaction and the sinks. Identical consecutive messages are collapsed into one
+// "repeated N times" line, and every distinct message gets its own token bucket so two messages
+// alternating in a loop can't flood the log either.
+pub struct Throttle {
+    last: Option<(Level, String)>,
+    // Whether `last` made it into the log. Repeats of one that was suppressed are suppressed too,
+    // "repeated N times" would refer to a line that is not there.
+    last_written: bool,
+    repeats: u64,
+    buckets: BTreeMap<String, Bucket>,
+    suppressed: BTreeMap<String, u64>,
+}
+
+impl Throttle {
+    pub const fn new() -> Self {
+        Throttle {
+            last: None,
+            last_written: false,
+            repeats: 0,
+            buckets: BTreeMap::new(),
+            suppressed: BTreeMap::new(),
+        }
+    }
+
+    // Returns the records that should actually be written, in order
+    pub fn admit(&mut self, level: Level, message: &str, now: Instant) -> Vec<(Level, String)> {
+        if let Some((last_level, last_message)) = &self.last {
+            if *last_level == level && last_message == message {
+                if self.last_written {
+                    self.repeats += 1;
+                } else {
+                    *self.suppressed.entry(message.to_string()).or_insert(0) += 1;
+                }
+                return Vec::new();
+            }
+        }
+
+        let mut records = self.take_repeats();
+        self.last = Some((level, message.to_string()));
+
+        self.last_written = self.take_token(message, now);
+        if self.last_written {
+            records.push((level, message.to_string()));
+        } else {
+            *self.suppressed.entry(message.to_string()).or_insert(0) += 1;
+        }
+        records
+    }
+
+    // Everything still pending, called at shutdown
+    pub fn finish(&mut self) -> Vec<(Level, String)> {
+        let mut records = self.take_repeats();
+        self.last = None;
+
+        for (message, count) in std::mem::take(&mut self.suppressed) {
+            records.push((
+                Level::Warn,
+                format!("rate limit suppressed {} more of: {}", count, message),
+            ));
+        }
+        records
+    }
+
+    fn take_repeats(&mut self) -> Vec<(Level, String)> {
+        let repeats = std::mem::take(&mut self.repeats);
+        match &self.last {
+            Some((level, _)) if repeats > 0 => {
+                vec![(*level, format!("last message repeated {} times", repeats))]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn take_token(&mut self, message: &str, now: Instant) -> bool {
+        if self.buckets.len() > MAX_TRACKED {
+            self.buckets.retain(|_, bucket| {
+                bucket.tokens + now.saturating_duration_since(bucket.updated).as_secs_f64() * REFILL_PER_SEC
+                    < BUCKET_CAPACITY
+            });
+        }
+
+        let bucket = self.buckets.entry(message.to_string()).or_insert(Bucket {
+            tokens: BUCKET_CAPACITY,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * REFILL_PER_SEC).min(BUCKET_CAPACITY);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn messages(records: &[(Level, String)]) -> Vec<&str> {
+        records.iter().map(|(_, message)| message.as_str()).collect()
+    }
+
+    #[test]
+    fn collapses_consecutive_duplicates() {
+        let mut throttle = Throttle::new();
+        let now = Instant::now();
+        let mut written = Vec::new();
+        for _ in 0..1000 {
+            written.extend(throttle.admit(Level::Warn, "Failed to flush stdout", now));
+        }
+        written.extend(throttle.admit(Level::Error, "File not found", now));
+        written.extend(throttle.finish());
+
+        assert_eq!(
+            messages(&written),
+            ["Failed to flush stdout", "last message repeated 999 times", "File not found"]
+        );
+    }
+
+    #[test]
+    fn rate_limits_alternating_messages() {
+        let mut throttle = Throttle::new();
+        let now = Instant::now();
+        let mut written = Vec::new();
+        for _ in 0..50 {
+            written.extend(throttle.admit(Level::Warn, "a", now));
+            written.extend(throttle.admit(Level::Warn, "b", now));
+        }
+        assert_eq!(written.len(), 2 * BUCKET_CAPACITY as usize);
+
+        // Two seconds later the bucket has refilled enough for two more
+        let later = now + Duration::from_secs(2);
+        written.clear();
+        for _ in 0..5 {
+            written.extend(throttle.admit(Level::Warn, "a", later));
+            written.extend(throttle.admit(Level::Warn, "b", later));
+        }
+        assert_eq!(written.len(), 4);
+
+        let summary = throttle.finish();
+        assert_eq!(
+            messages(&summary),
+            ["rate limit suppressed 43 more of: a", "rate limit suppressed 43 more of: b"]
+        );
+    }
+
+    #[test]
+    fn counts_repeats_of_suppressed_messages_as_suppressed() {
+        let mut throttle = Throttle::new();
+        let now = Instant::now();
+        let mut written = Vec::new();
+        for _ in 0..BUCKET_CAPACITY as usize {
+            written.extend(throttle.admit(Level::Warn, "a", now));
+            written.extend(throttle.admit(Level::Warn, "b", now));
+        }
+        // The bucket for "a" is empty, so this and its repeats never reach the log
+        for _ in 0..5 {
+            written.extend(throttle.admit(Level::Warn, "a", now));
+        }
+        written.extend(throttle.admit(Level::Warn, "c", now));
+        written.extend(throttle.finish());
+
+        assert!(!written.iter().any(|(_, message)| message.starts_with("last message repeated")));
+        assert_eq!(messages(&written[written.len() - 2..]), ["c", "rate limit suppressed 5 more of: a"]);
+    }
+}
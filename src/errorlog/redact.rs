@@ -0,0 +1,200 @@
+use regex::{Captures, Regex};
+
+const MASK: &str = "[REDACTED]";
+
+// Field names whose values are masked by default, matched case-insensitively as `name=value` or `name: value`
+const DEFAULT_FIELDS: [&str; 6] = ["password", "passphrase", "passwd", "secret", "token", "api_key"];
+
+// Rules applied to every log record before it is written anywhere.
+// Order matters: field masking runs first, then custom patterns, then home directory masking,
+// so a pattern can still match the full path of a file before it is shortened to ~.
+pub struct Redactor {
+    fields: Vec<String>,
+    field_regex: Option<Regex>,
+    patterns: Vec<Regex>,
+    homes: Vec<String>,
+    home_regex: Option<Regex>,
+}
+
+impl Redactor {
+    pub const fn new() -> Self {
+        Redactor {
+            fields: Vec::new(),
+            field_regex: None,
+            patterns: Vec::new(),
+            homes: Vec::new(),
+            home_regex: None,
+        }
+    }
+
+    // The rules the application starts with: common secret field names and the user's home directory
+    pub fn with_defaults() -> Self {
+        let mut redactor = Redactor::new();
+        for field in DEFAULT_FIELDS {
+            redactor = redactor.with_field(field);
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            redactor = redactor.with_home_dir(&home.to_string_lossy());
+        }
+        redactor
+    }
+
+    // Masks the value in `field=value`, `field: value` and `field="quoted value"`
+    pub fn with_field(mut self, field: &str) -> Self {
+        self.fields.push(regex::escape(field));
+        let pattern = format!(
+            r#"(?i)\b({})(\s*[=:]\s*)("[^"]*"|'[^']*'|[^\s,;&]+)"#,
+            self.fields.join("|")
+        );
+        self.field_regex = Regex::new(&pattern).ok();
+        self
+    }
+
+    // Every match of `pattern` is replaced with the mask
+    pub fn with_pattern(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.patterns.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    // Paths under `home` are shortened to `~` so user names don't end up in the log. Each call
+    // adds a directory, the ones added before keep being masked.
+    pub fn with_home_dir(mut self, home: &str) -> Self {
+        let home = home.trim_end_matches('/');
+        if home.is_empty() {
+            return self;
+        }
+        self.homes.push(regex::escape(home));
+        // Longest first, so /home/al does not win over /home/alice and then fail the end check
+        self.homes.sort_by_key(|home| std::cmp::Reverse(home.len()));
+        // Only match where a path starts, /root must not be found inside /tmp/sbx/root. Where it
+        // ends is checked in redact, the regex crate has no lookahead.
+        let pattern = format!(r"(^|[\s'\x22=:(\[])({})", self.homes.join("|"));
+        self.home_regex = Regex::new(&pattern).ok();
+        self
+    }
+
+    // Extra rules from a config file, one per line: `field <name>`, `pattern <regex>` or `home <dir>`.
+    // Blank lines and lines starting with # are skipped.
+    pub fn with_rules(mut self, rules: &str) -> Result<Self, String> {
+        for (number, line) in rules.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (kind, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            self = match kind {
+                "field" if !value.is_empty() => self.with_field(value),
+                "home" if !value.is_empty() => self.with_home_dir(value),
+                "pattern" if !value.is_empty() => self
+                    .with_pattern(value)
+                    .map_err(|e| format!("line {}: invalid pattern: {}", number + 1, e))?,
+                _ => return Err(format!("line {}: unknown rule '{}'", number + 1, line)),
+            };
+        }
+        Ok(self)
+    }
+
+    pub fn redact(&self, message: &str) -> String {
+        let mut message = message.to_string();
+
+        if let Some(field_regex) = &self.field_regex {
+            message = field_regex
+                .replace_all(&message, format!("${{1}}${{2}}{}", MASK))
+                .into_owned();
+        }
+
+        for pattern in &self.patterns {
+            message = pattern.replace_all(&message, MASK).into_owned();
+        }
+
+        if let Some(home_regex) = &self.home_regex {
+            let text = &message;
+            let shortened = home_regex.replace_all(text, |caps: &Captures| {
+                // Only whole path components, /home/al must not eat the start of /home/alice
+                let after = text[caps.get(0).unwrap().end()..].chars().next();
+                match after {
+                    None | Some('/' | '\'' | '"' | ':' | ',' | ';' | ')' | ']') => format!("{}~", &caps[1]),
+                    Some(c) if c.is_whitespace() => format!("{}~", &caps[1]),
+                    Some(_) => caps[0].to_string(),
+                }
+            });
+            message = shortened.into_owned();
+        }
+
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_field_values() {
+        let redactor = Redactor::new().with_field("password").with_field("token");
+        assert_eq!(
+            redactor.redact("login failed password=hunter2 user=bob"),
+            "login failed password=[REDACTED] user=bob"
+        );
+        assert_eq!(redactor.redact("TOKEN: abc123, retrying"), "TOKEN: [REDACTED], retrying");
+        assert_eq!(redactor.redact(r#"password="two words""#), "password=[REDACTED]");
+    }
+
+    #[test]
+    fn masks_custom_patterns() {
+        let redactor = Redactor::new().with_pattern(r"\b\d{4}-\d{4}-\d{4}-\d{4}\b").unwrap();
+        assert_eq!(redactor.redact("card 1234-5678-9012-3456 declined"), "card [REDACTED] declined");
+        assert!(Redactor::new().with_pattern("(unclosed").is_err());
+    }
+
+    #[test]
+    fn loads_rules_from_config() {
+        let rules = "# comment\n\nfield pin\npattern \\d{3}-\\d{2}-\\d{4}\n";
+        let redactor = Redactor::new().with_rules(rules).unwrap();
+        assert_eq!(redactor.redact("pin=1234 ssn 123-45-6789"), "pin=[REDACTED] ssn [REDACTED]");
+        assert!(Redactor::new().with_rules("mask everything").is_err());
+
+        // Homes from the config come on top of the default one
+        let redactor = Redactor::new().with_home_dir("/home/al").with_rules("home /srv/alice\nhome /home/alice").unwrap();
+        assert_eq!(
+            redactor.redact("copied /home/al/a.txt /srv/alice/b.txt, /home/alice (/home/alicia)"),
+            "copied ~/a.txt ~/b.txt, ~ (/home/alicia)"
+        );
+        assert!(Redactor::new().with_rules("pattern [").is_err());
+    }
+
+    #[test]
+    fn masks_home_directory() {
+        let redactor = Redactor::new().with_home_dir("/home/alice/");
+        assert_eq!(
+            redactor.redact("File not found: /home/alice/notes.txt"),
+            "File not found: ~/notes.txt"
+        );
+        assert_eq!(redactor.redact("cwd is /home/alice"), "cwd is ~");
+        assert_eq!(redactor.redact("/home/alicia/notes.txt"), "/home/alicia/notes.txt");
+        assert_eq!(redactor.redact("path=/home/alice/a (/home/alice)"), "path=~/a (~)");
+
+        // The end of one path is not used up, the next one right after it is masked too
+        assert_eq!(redactor.redact("moved /home/alice /home/alice/x"), "moved ~ ~/x");
+
+        let redactor = Redactor::new().with_home_dir("/root");
+        assert_eq!(
+            redactor.redact("/tmp/sbx/root/evil leads outside /root/x"),
+            "/tmp/sbx/root/evil leads outside ~/x"
+        );
+    }
+
+    #[test]
+    fn keeps_the_default_home_next_to_configured_ones() {
+        let home = match std::env::var("HOME") {
+            Ok(home) if !home.trim_end_matches('/').is_empty() => home.trim_end_matches('/').to_string(),
+            _ => return,
+        };
+        let redactor = Redactor::with_defaults().with_rules("home /srv/alice").unwrap();
+        assert_eq!(
+            redactor.redact(&format!("moved {} {}/x /srv/alice /srv/alice/y", home, home)),
+            "moved ~ ~/x ~ ~/y"
+        );
+    }
+}
@@ -1,92 +1,220 @@
-use std::io;
+mod atomic;
+mod browse;
+mod checksum;
+mod crypt;
+mod csv;
+mod diff;
+mod editor;
+mod encoding;
+mod formats;
+mod hexdump;
+mod interactive;
+mod manage;
+mod message;
+mod query;
+mod sandbox;
+mod search;
+mod stats;
+mod stream;
+mod tar;
+mod transform;
+mod watch;
+
 use std::io::Write;
-use std::fs::{File, OpenOptions};
-use std::io::Read;
-use crate::read_input;
-use crate::errorlog::log_error;
-
-pub fn read_file() {
-    loop {
-        print!("Enter file path or ('q') to exit: ");
-        io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
-
-        let file_path = read_input();
-        if file_path == "q" {
-            break;
+use std::fs::{self, File, OpenOptions};
+use crate::apperror::AppError;
+
+pub use atomic::{write_atomic, write_atomic_with};
+pub use browse::{format_size, format_time, list_dir, parent_dir, suggest_paths};
+pub use checksum::{checksum, verify, Algorithm};
+pub use crypt::{is_encrypted_file, read_encrypted, write_encrypted};
+pub use csv::{delimiter_for, format_csv, format_table, parse_csv, read_csv};
+pub use diff::{diff, DiffOptions};
+pub use editor::Editor;
+pub use encoding::{convert_line_endings, decode, detect_data_encoding, detect_encoding, detect_file_encoding, detect_file_layout, encode, read_decoded, Encoding, LineEnding};
+pub use formats::{detect_file_format, render, Format};
+pub use hexdump::{hexdump, parse_number, HexDumpOptions};
+pub use interactive::{append_to_file, file_tools, read_file, write_into_file};
+pub use manage::{copy, make_dir, move_path, Trash};
+pub use message::{preview, read_until_sentinel, SENTINEL};
+pub use query::Query;
+pub use sandbox::{exists, resolve, root, set_root};
+pub use search::{build_matcher, search, SearchOptions};
+pub use stats::{stats_report, text_stats};
+pub use stream::{for_each_line, InvalidUtf8, LineSelection};
+pub use tar::{create_tar, extract_tar, list_tar, EntryKind, TarEntry};
+pub use transform::{transform_file, Pipeline};
+pub use watch::{watch, WatchMode, WatchOptions};
+
+// Core file operations. Nothing in here prompts or prints, the menu handlers in
+// fileio/interactive.rs and the `file` subcommands in cli.rs are thin wrappers around these.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteMode {
+    // Only write a new file, fails if the path already exists
+    Create,
+    // Only replace an existing file, fails if it does not exist
+    Truncate,
+    // Create the file or replace whatever is there
+    Overwrite,
+}
+
+impl WriteMode {
+    pub fn parse(name: &str) -> Option<WriteMode> {
+        match name {
+            "create" => Some(WriteMode::Create),
+            "truncate" => Some(WriteMode::Truncate),
+            "overwrite" => Some(WriteMode::Overwrite),
+            _ => None,
         }
-        let mut file = match File::open(&file_path) {
-            Ok(file) => file,
-            Err(_) => {
-                log_error("File not found. Please enter a valid file path.");
-                continue;
-            }
-        };
-
-        let mut file_contents = String::new();
-        file.read_to_string(&mut file_contents)
-            .unwrap_or_else(|_| {
-                log_error("Failed to read file");
-                0 // Return a default usize value (e.g., 0) on error
-            });        println!("File contents:\n{}", file_contents);
-        break;
     }
 }
 
-fn create_file(path: &str) -> Result<File, io::Error> {
-    File::create(path)
+// Whole-file read for small files, the menu and `file cat` stream through for_each_line instead
+pub fn read_to_string(path: &str) -> Result<String, AppError> {
+    fs::read_to_string(resolve(path)?).map_err(|e| AppError::io("read", path, e))
 }
 
-fn read_message() -> String {
-    let mut message = String::new();
-    print!("Enter message to write to file: ");
-    io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
-
-    io::stdin().read_line(&mut message)
-        .unwrap_or_else(|_| {
-            log_error("Failed to read line");
-            0 // Return a default usize value (e.g., 0) on error
-        });
-    return message;
+pub fn write(path: &str, bytes: &[u8], mode: WriteMode) -> Result<(), AppError> {
+    let mut file = open_for_write(path, mode)?;
+    file.write_all(bytes).map_err(|e| AppError::io("write to", path, e))
 }
 
-pub fn write_into_file() {
-    print!("Enter file path to write to: ");
-    io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
-    let path = read_input();
-
-    let mut file = match File::open(&path) {
-        Ok(file) => file,
-        Err(_) => match create_file(&path) {
-            Ok(file) => file,
-            Err(e) => {
-                log_error(format!("Failed to create file: {}", e));
-                return;
-            }
-        },
+// For writers that stream instead of passing all bytes to write
+pub fn open_for_write(path: &str, mode: WriteMode) -> Result<File, AppError> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    match mode {
+        WriteMode::Create => options.create_new(true),
+        WriteMode::Truncate => options.truncate(true),
+        WriteMode::Overwrite => options.create(true).truncate(true),
     };
+    options.open(resolve(path)?).map_err(|e| AppError::io("open", path, e))
+}
 
-    let message = read_message();
-    file.write_all(message.as_bytes()).unwrap_or_else(|_| log_error("Failed to write to file"));
-    println!("Message written to file successfully!");
+// Creates the file if needed and adds to the end of it. Encrypted files are refused, anything
+// after their authentication tag would make them unreadable.
+pub fn append(path: &str, bytes: &[u8]) -> Result<(), AppError> {
+    if exists(path) && is_encrypted_file(path)? {
+        return Err(AppError::crypto(path, "the file is encrypted, it can only be written as a whole"));
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(resolve(path)?)
+        .map_err(|e| AppError::io("open", path, e))?;
+    file.write_all(bytes).map_err(|e| AppError::io("append to", path, e))
 }
 
-pub fn append_to_file() {
-    print!("Enter file path to append to: ");
-    io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
-    let path = read_input();
-
-    let mut file = match OpenOptions::new().append(true).open(&path) {
-        Ok(file) => file,
-        Err(_) => match create_file(&path) {
-            Ok(file) => file,
-            Err(e) => {
-                log_error(format!("Failed to create file: {}", e));
-                return;
-            }
-        },
+// Appends text in the encoding and line endings the file already has, so appending to a UTF-16
+// or Latin-1 file keeps it readable. `ending` overrides the file's line endings. New, empty and
+// binary files get UTF-8.
+pub fn append_text(path: &str, text: &str, ending: Option<LineEnding>) -> Result<(), AppError> {
+    let (encoding, existing) = if exists(path) { detect_file_layout(path)? } else { (None, None) };
+    let text = match ending.or(existing) {
+        Some(ending) => convert_line_endings(text, ending),
+        None => text.to_string(),
     };
+    let bytes = match encoding {
+        None | Some(Encoding::Utf8) => text.into_bytes(),
+        Some(encoding) => {
+            let mut bytes = encode(&text, encoding)?;
+            // The file already starts with its byte order mark
+            let bom = encode("", encoding)?.len();
+            bytes.drain(..bom);
+            bytes
+        }
+    };
+    append(path, &bytes)
+}
+
+// A fresh, empty directory per test so tests can run in parallel
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("underpass-fileio-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn path_in(dir: &Path, name: &str) -> String {
+        dir.join(name).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn create_mode_only_writes_new_files() {
+        let dir = test_dir("create");
+        let path = path_in(&dir, "notes.txt");
 
-    let message = read_message();
-    file.write_all(message.as_bytes()).unwrap_or_else(|_| log_error("Failed to write to file"));
-    println!("Message appended to file successfully!");
-}
\ No newline at end of file
+        write(&path, b"first", WriteMode::Create).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "first");
+        assert!(write(&path, b"second", WriteMode::Create).is_err());
+        assert_eq!(read_to_string(&path).unwrap(), "first");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncate_mode_only_replaces_existing_files() {
+        let dir = test_dir("truncate");
+        let path = path_in(&dir, "notes.txt");
+
+        assert!(write(&path, b"text", WriteMode::Truncate).is_err());
+        assert!(read_to_string(&path).is_err());
+
+        fs::write(&path, "a much longer original").unwrap();
+        write(&path, b"short", WriteMode::Truncate).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "short");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn overwrite_mode_creates_or_replaces() {
+        let dir = test_dir("overwrite");
+        let path = path_in(&dir, "notes.txt");
+
+        write(&path, b"a much longer original", WriteMode::Overwrite).unwrap();
+        write(&path, b"short", WriteMode::Overwrite).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "short");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn append_creates_then_extends() {
+        let dir = test_dir("append");
+        let path = path_in(&dir, "log.txt");
+
+        append(&path, b"one\n").unwrap();
+        append(&path, b"two\n").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "one\ntwo\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn read_reports_missing_files() {
+        let dir = test_dir("missing");
+        let e = read_to_string(&path_in(&dir, "nope.txt")).unwrap_err();
+        assert!(e.to_string().starts_with("File not found"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn appends_in_the_files_encoding() {
+        let dir = test_dir("append-encoding");
+        let path = path_in(&dir, "wide.txt");
+        fs::write(&path, encode("one\r\n", Encoding::Utf16Le).unwrap()).unwrap();
+        append_text(&path, "caf\u{e9}\n", None).unwrap();
+        assert_eq!(read_decoded(&path).unwrap(), (String::from("one\r\ncaf\u{e9}\r\n"), Some(Encoding::Utf16Le)));
+
+        let latin = path_in(&dir, "latin.txt");
+        fs::write(&latin, b"na\xEFve\n").unwrap();
+        append_text(&latin, "d\u{e9}j\u{e0}\n", None).unwrap();
+        assert_eq!(fs::read(&latin).unwrap(), b"na\xEFve\nd\xE9j\xE0\n");
+        assert!(append_text(&latin, "\u{20ac}", None).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::apperror::AppError;
+use super::{resolve, WriteMode};
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Writes to a temp file next to `path`, fsyncs it and renames it over the target.
+// A crash at any point leaves either the old contents or the new ones, never a truncated file.
+// With `backup` the previous contents are kept as `<path>.bak`.
+pub fn write_atomic(path: &str, bytes: &[u8], mode: WriteMode, backup: bool) -> Result<(), AppError> {
+    write_atomic_with(path, mode, backup, |temp, _| temp.write_all(bytes).map_err(|e| AppError::io("write to", path, e)))
+}
+
+// write_atomic for contents that are produced bit by bit, like an archive. `fill` gets the temp
+// file and its path, and what it returns is passed on once the temp file has replaced `path`.
+// If it fails the temp file is removed and `path` is left as it was.
+pub fn write_atomic_with<T, F>(path: &str, mode: WriteMode, backup: bool, fill: F) -> Result<T, AppError>
+where
+    F: FnOnce(&mut File, &Path) -> Result<T, AppError>,
+{
+    let resolved = resolve(path)?;
+    let target = Path::new(&resolved);
+    let existing = fs::metadata(target).ok();
+
+    match (mode, &existing) {
+        (WriteMode::Create, Some(_)) => {
+            let e = io::Error::new(io::ErrorKind::AlreadyExists, "file already exists");
+            return Err(AppError::io("create", path, e));
+        }
+        (WriteMode::Truncate, None) => {
+            let e = io::Error::new(io::ErrorKind::NotFound, "file does not exist");
+            return Err(AppError::io("open", path, e));
+        }
+        _ => {}
+    }
+
+    let (temp_path, mut temp) = create_temp(target).map_err(|e| AppError::io("create temp file for", path, e))?;
+
+    let filled = match fill(&mut temp, &temp_path) {
+        Ok(filled) => filled,
+        Err(e) => {
+            drop(temp);
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    };
+    let written = match &existing {
+        // Keep the permissions of the file being replaced
+        Some(metadata) => temp.set_permissions(metadata.permissions()),
+        None => Ok(()),
+    }
+    .and_then(|_| temp.sync_all());
+    drop(temp);
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(AppError::io("write to", temp_path.to_string_lossy(), e));
+    }
+
+    if backup && existing.is_some() {
+        let backup_path = format!("{}.bak", resolved);
+        if let Err(e) = fs::copy(target, &backup_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(AppError::io("back up", path, e));
+        }
+    }
+
+    let renamed = if mode == WriteMode::Create {
+        // hard_link fails if the target appeared since the check above, rename would silently replace it
+        fs::hard_link(&temp_path, target).and_then(|_| fs::remove_file(&temp_path))
+    } else {
+        fs::rename(&temp_path, target)
+    };
+    if let Err(e) = renamed {
+        let _ = fs::remove_file(&temp_path);
+        return Err(AppError::io("replace", path, e));
+    }
+
+    sync_parent(target).map_err(|e| AppError::io("sync directory of", path, e))?;
+    Ok(filled)
+}
+
+fn create_temp(target: &Path) -> io::Result<(PathBuf, File)> {
+    let name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy();
+
+    loop {
+        let temp_name = format!(
+            ".{}.tmp-{}-{}",
+            name,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let temp_path = target.with_file_name(temp_name);
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// The rename itself only survives a crash once the directory entry is on disk
+fn sync_parent(target: &Path) -> io::Result<()> {
+    let parent = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fileio::test_dir;
+
+    #[test]
+    fn replaces_contents_and_keeps_backup() {
+        let dir = test_dir("atomic-backup");
+        let path = dir.join("config.toml");
+        let path = path.to_str().unwrap();
+        fs::write(path, "old = true\n").unwrap();
+
+        write_atomic(path, b"new = true\n", WriteMode::Overwrite, true).unwrap();
+
+        assert_eq!(fs::read_to_string(path).unwrap(), "new = true\n");
+        assert_eq!(fs::read_to_string(format!("{}.bak", path)).unwrap(), "old = true\n");
+        // Only the target and its backup are left, no temp files
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn respects_write_mode() {
+        let dir = test_dir("atomic-mode");
+        let path = dir.join("notes.txt");
+        let path = path.to_str().unwrap();
+
+        assert!(write_atomic(path, b"x", WriteMode::Truncate, false).is_err());
+        write_atomic(path, b"first", WriteMode::Create, false).unwrap();
+        assert!(write_atomic(path, b"second", WriteMode::Create, false).is_err());
+        assert_eq!(fs::read_to_string(path).unwrap(), "first");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::apperror::AppError;
+use super::{resolve, root};
+
+// At most this many completions are offered for a mistyped path
+const MAX_SUGGESTIONS: usize = 10;
+
+pub struct DirEntryInfo {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+// Directories first, then files, each sorted by name
+pub fn list_dir(dir: &Path) -> Result<Vec<DirEntryInfo>, AppError> {
+    let shown = dir.to_string_lossy();
+    let mut entries: Vec<DirEntryInfo> = fs::read_dir(resolve(&shown)?)
+        .map_err(|e| AppError::io("list", shown.as_ref(), e))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            // fs::metadata follows symlinks, so a link to a directory can be entered
+            let metadata = fs::metadata(entry.path()).ok();
+            DirEntryInfo {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: metadata.as_ref().is_some_and(|m| m.is_dir()),
+                size: metadata.as_ref().map_or(0, |m| m.len()),
+                modified: metadata.and_then(|m| m.modified().ok()),
+            }
+        })
+        .collect();
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+// Completions for a path that does not exist, like pressing tab in a shell:
+// entries of the parent directory that start with the last component, case-insensitive
+// when nothing matches exactly. Directories end in '/'.
+pub fn suggest_paths(input: &str) -> Vec<String> {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(index) => (&input[..=index], &input[index + 1..]),
+        None => ("", input),
+    };
+    let dir_path = if dir.is_empty() { Path::new(".") } else { Path::new(dir) };
+    let entries = match list_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let complete = |entry: &DirEntryInfo| {
+        let slash = if entry.is_dir { "/" } else { "" };
+        format!("{}{}{}", dir, entry.name, slash)
+    };
+    let mut suggestions: Vec<String> = entries
+        .iter()
+        .filter(|entry| entry.name.starts_with(prefix))
+        .map(complete)
+        .collect();
+    if suggestions.is_empty() {
+        let lower = prefix.to_lowercase();
+        suggestions = entries
+            .iter()
+            .filter(|entry| entry.name.to_lowercase().starts_with(&lower))
+            .map(complete)
+            .collect();
+    }
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
+
+pub fn parent_dir(dir: &Path) -> PathBuf {
+    // canonicalize turns "." into a real path, so ".." works from the start directory too
+    let resolved = resolve(&dir.to_string_lossy()).map_or_else(|_| dir.to_path_buf(), PathBuf::from);
+    let dir = fs::canonicalize(&resolved).unwrap_or(resolved);
+    match (dir.parent(), root()) {
+        // Going up stops at the root directory
+        (Some(_), Some(root)) if dir == root => dir,
+        (Some(parent), _) => parent.to_path_buf(),
+        (None, _) => dir,
+    }
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+// "YYYY-MM-DD HH:MM" in UTC
+pub fn format_time(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => return String::from("-"),
+    };
+    let days = seconds.div_euclid(86400);
+    let minutes = seconds.rem_euclid(86400) / 60;
+
+    // Days since 1970-01-01 to a calendar date, Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::fileio::test_dir;
+
+    #[test]
+    fn suggests_completions() {
+        let dir = test_dir("browse");
+        fs::create_dir_all(dir.join("reports")).unwrap();
+        fs::write(dir.join("readme.txt"), "").unwrap();
+        fs::write(dir.join("Results.csv"), "").unwrap();
+        let base = format!("{}/", dir.to_string_lossy());
+
+        let suggestions = suggest_paths(&format!("{}re", base));
+        assert_eq!(suggestions, [format!("{}reports/", base), format!("{}readme.txt", base)]);
+        assert_eq!(suggest_paths(&format!("{}RES", base)), [format!("{}Results.csv", base)]);
+        assert!(suggest_paths(&format!("{}zzz", base)).is_empty());
+        assert!(suggest_paths(&format!("{}missing/re", base)).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn formats_sizes_and_times() {
+        assert_eq!(format_size(512), "512B");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0M");
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00");
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(1_709_210_096)), "2024-02-29 12:34");
+    }
+}
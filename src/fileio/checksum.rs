@@ -0,0 +1,515 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use crate::apperror::AppError;
+
+// Files are hashed in pieces of this size, so nothing is ever read into memory whole
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Crc32,
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 4] = [Algorithm::Crc32, Algorithm::Md5, Algorithm::Sha1, Algorithm::Sha256];
+
+    pub fn parse(name: &str) -> Option<Algorithm> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "crc32" => Some(Algorithm::Crc32),
+            "md5" => Some(Algorithm::Md5),
+            "sha1" => Some(Algorithm::Sha1),
+            "sha256" => Some(Algorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    // Manifests do not say which algorithm made them, but the digest length gives it away
+    fn from_hex_len(len: usize) -> Option<Algorithm> {
+        Algorithm::ALL.into_iter().find(|algorithm| algorithm.hex_len() == len)
+    }
+
+    fn hex_len(self) -> usize {
+        match self {
+            Algorithm::Crc32 => 8,
+            Algorithm::Md5 => 32,
+            Algorithm::Sha1 => 40,
+            Algorithm::Sha256 => 64,
+        }
+    }
+
+    fn hasher(self) -> Box<dyn Digest> {
+        match self {
+            Algorithm::Crc32 => Box::new(Crc32::new()),
+            Algorithm::Md5 => Box::new(Md5::new()),
+            Algorithm::Sha1 => Box::new(Sha1::new()),
+            Algorithm::Sha256 => Box::new(Sha256::new()),
+        }
+    }
+}
+
+trait Digest {
+    fn update(&mut self, data: &[u8]);
+    fn finish(&mut self) -> Vec<u8>;
+}
+
+// Digest of a file as lowercase hex, read in chunks
+pub fn checksum(path: &str, algorithm: Algorithm) -> Result<String, AppError> {
+    let mut file = File::open(super::resolve(path)?).map_err(|e| AppError::io("open", path, e))?;
+    let mut hasher = algorithm.hasher();
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut chunk).map_err(|e| AppError::io("read", path, e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&chunk[..read]);
+    }
+    Ok(to_hex(&hasher.finish()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VerifySummary {
+    pub ok: usize,
+    pub failed: usize,
+    // Files that could not be read, or manifest lines that could not be understood
+    pub unreadable: usize,
+}
+
+// Checks every file listed in a manifest in the format sha256sum and friends write:
+//     <hex digest>  <path>        (or " *<path>" for binary mode)
+// Relative paths are taken from the manifest's directory. The algorithm is guessed from the digest
+// length unless one is given. Reports "path: OK" or "path: FAILED ..." for each entry to `on_result`.
+pub fn verify<F>(manifest: &str, algorithm: Option<Algorithm>, mut on_result: F) -> Result<VerifySummary, AppError>
+where
+    F: FnMut(&str) -> bool,
+{
+    let base = Path::new(manifest).parent().unwrap_or(Path::new(""));
+    let mut summary = VerifySummary::default();
+    let mut stopped = false;
+
+    super::for_each_line(manifest, super::LineSelection::All, super::InvalidUtf8::Lossy, |number, line| {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return true;
+        }
+        let report = match parse_manifest_line(line) {
+            Some((expected, name)) => {
+                let path = base.join(name);
+                let path = path.to_string_lossy();
+                match algorithm.or_else(|| Algorithm::from_hex_len(expected.len())) {
+                    None => {
+                        summary.unreadable += 1;
+                        format!("{}: FAILED unknown digest length {}", name, expected.len())
+                    }
+                    Some(algorithm) => match checksum(&path, algorithm) {
+                        Ok(actual) if actual.eq_ignore_ascii_case(expected) => {
+                            summary.ok += 1;
+                            format!("{}: OK", name)
+                        }
+                        Ok(_) => {
+                            summary.failed += 1;
+                            format!("{}: FAILED", name)
+                        }
+                        Err(e) => {
+                            summary.unreadable += 1;
+                            format!("{}: FAILED {}", name, e)
+                        }
+                    },
+                }
+            }
+            None => {
+                summary.unreadable += 1;
+                format!("{}:{}: not a checksum line", manifest, number)
+            }
+        };
+        if !on_result(&report) {
+            stopped = true;
+        }
+        !stopped
+    })?;
+    Ok(summary)
+}
+
+fn parse_manifest_line(line: &str) -> Option<(&str, &str)> {
+    let (digest, rest) = line.split_once(' ')?;
+    let name = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+    if digest.is_empty() || name.is_empty() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((digest, name))
+}
+
+// CRC-32 as used by zip, gzip and PNG (reflected polynomial 0xEDB88320)
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut value = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 == 1 { 0xEDB88320 ^ (value >> 1) } else { value >> 1 };
+            bit += 1;
+        }
+        table[n] = value;
+        n += 1;
+    }
+    table
+}
+
+struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        Crc32 { value: 0xFFFFFFFF }
+    }
+}
+
+impl Digest for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.value = CRC32_TABLE[((self.value ^ *byte as u32) & 0xFF) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        (self.value ^ 0xFFFFFFFF).to_be_bytes().to_vec()
+    }
+}
+
+// MD5, SHA-1 and SHA-256 all work on 64 byte blocks and pad the same way, only the byte order of
+// the length at the end differs. This collects the input into blocks for them.
+struct Blocks {
+    buffer: [u8; 64],
+    filled: usize,
+    total: u64,
+}
+
+impl Blocks {
+    fn new() -> Self {
+        Blocks { buffer: [0; 64], filled: 0, total: 0 }
+    }
+
+    fn update(&mut self, mut data: &[u8], compress: &mut dyn FnMut(&[u8; 64])) {
+        self.total += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.filled).min(data.len());
+            self.buffer[self.filled..self.filled + take].copy_from_slice(&data[..take]);
+            self.filled += take;
+            data = &data[take..];
+            if self.filled == 64 {
+                compress(&self.buffer);
+                self.filled = 0;
+            }
+        }
+    }
+
+    // 0x80, zeros up to 56 bytes into a block, then the length in bits
+    fn finish(&mut self, big_endian: bool, compress: &mut dyn FnMut(&[u8; 64])) {
+        let bits = self.total.wrapping_mul(8);
+        let length = if big_endian { bits.to_be_bytes() } else { bits.to_le_bytes() };
+        let zeros = (119 - (self.total % 64) as usize) % 64;
+        let mut padding = vec![0x80];
+        padding.resize(1 + zeros, 0);
+        padding.extend_from_slice(&length);
+        let total = self.total;
+        self.update(&padding, compress);
+        self.total = total;
+    }
+}
+
+struct Md5 {
+    state: [u32; 4],
+    blocks: Blocks,
+}
+
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+// floor(abs(sin(i + 1)) * 2^32)
+const MD5_CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+impl Md5 {
+    fn new() -> Self {
+        Md5 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn compress(state: &mut [u32; 4], block: &[u8; 64]) {
+        let words: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = *state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(MD5_CONSTANTS[i])
+                .wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+}
+
+impl Digest for Md5 {
+    fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, &mut |block| Md5::compress(state, block));
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let state = &mut self.state;
+        self.blocks.finish(false, &mut |block| Md5::compress(state, block));
+        self.state.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+}
+
+struct Sha1 {
+    state: [u32; 5],
+    blocks: Blocks,
+}
+
+impl Sha1 {
+    fn new() -> Self {
+        Sha1 {
+            state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0],
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn compress(state: &mut [u32; 5], block: &[u8; 64]) {
+        let mut words = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            words[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = *state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5A827999),
+                1 => (b ^ c ^ d, 0x6ED9EBA1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+}
+
+impl Digest for Sha1 {
+    fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, &mut |block| Sha1::compress(state, block));
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let state = &mut self.state;
+        self.blocks.finish(true, &mut |block| Sha1::compress(state, block));
+        self.state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+}
+
+struct Sha256 {
+    state: [u32; 8],
+    blocks: Blocks,
+}
+
+// First 32 bits of the fractional parts of the cube roots of the first 64 primes
+const SHA256_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+impl Sha256 {
+    fn new() -> Self {
+        Sha256 {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+            ],
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn compress(state: &mut [u32; 8], block: &[u8; 64]) {
+        let mut words = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            words[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = words[i - 15].rotate_right(7) ^ words[i - 15].rotate_right(18) ^ (words[i - 15] >> 3);
+            let s1 = words[i - 2].rotate_right(17) ^ words[i - 2].rotate_right(19) ^ (words[i - 2] >> 10);
+            words[i] = words[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(words[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA256_CONSTANTS[i])
+                .wrapping_add(words[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+}
+
+impl Digest for Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, &mut |block| Sha256::compress(state, block));
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let state = &mut self.state;
+        self.blocks.finish(true, &mut |block| Sha256::compress(state, block));
+        self.state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::fileio::test_dir;
+
+    fn digest_bytes(algorithm: Algorithm, data: &[u8]) -> String {
+        let mut hasher = algorithm.hasher();
+        hasher.update(data);
+        to_hex(&hasher.finish())
+    }
+
+    #[test]
+    fn matches_known_digests() {
+        assert_eq!(digest_bytes(Algorithm::Crc32, b"123456789"), "cbf43926");
+        assert_eq!(digest_bytes(Algorithm::Md5, b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(digest_bytes(Algorithm::Md5, b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(digest_bytes(Algorithm::Sha1, b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            digest_bytes(Algorithm::Sha256, b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        // Two blocks once padded
+        assert_eq!(
+            digest_bytes(Algorithm::Sha256, b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn streams_files_and_verifies_manifests() {
+        let dir = test_dir("checksum");
+        // A million 'a's, the long test vector of the SHA standards. Bigger than one chunk and not
+        // a multiple of it, so the streaming has to carry partial blocks over.
+        let data = vec![b'a'; 1_000_000];
+        assert!(data.len() > CHUNK_SIZE && data.len() % CHUNK_SIZE != 0);
+        fs::write(dir.join("big.bin"), &data).unwrap();
+        fs::write(dir.join("small.txt"), "abc").unwrap();
+        let big = "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0";
+        let known = [
+            (Algorithm::Crc32, "dc25bfbc"),
+            (Algorithm::Md5, "7707d6ae4e027c70eea2a935c2296f21"),
+            (Algorithm::Sha1, "34aa973cd4c4daa4f61eeb2bdbad27316534016f"),
+            (Algorithm::Sha256, big),
+        ];
+        for (algorithm, digest) in known {
+            let path = dir.join("big.bin");
+            assert_eq!(checksum(path.to_str().unwrap(), algorithm).unwrap(), digest);
+        }
+
+        let manifest = dir.join("SHA256SUMS");
+        let text = format!(
+            "{}  big.bin\n{} *small.txt\n900150983cd24fb0d6963f7d28e17f72  missing.txt\n0000  small.txt\ngarbage\n",
+            big,
+            digest_bytes(Algorithm::Md5, b"abd"),
+        );
+        fs::write(&manifest, text).unwrap();
+        let mut results = Vec::new();
+        let summary = verify(manifest.to_str().unwrap(), None, |line| {
+            results.push(line.to_string());
+            true
+        })
+        .unwrap();
+        assert_eq!(summary, VerifySummary { ok: 1, failed: 1, unreadable: 3 });
+        assert_eq!(results[0], "big.bin: OK");
+        assert_eq!(results[1], "small.txt: FAILED");
+        assert!(results[2].starts_with("missing.txt: FAILED File not found"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
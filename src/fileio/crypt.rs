@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::Read;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, Generate, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use crate::apperror::AppError;
+use super::{resolve, write_atomic, WriteMode};
+
+// Every encrypted file starts with this, so it is recognisable even in a text editor
+pub const MAGIC: &[u8] = b"UNDERPASS-ENCRYPTED v1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+// The magic, the Argon2 memory, iterations and lanes as little endian u32s, the salt and the
+// nonce. The ciphertext with its tag follows.
+const HEADER_LEN: usize = MAGIC.len() + 12 + SALT_LEN + NONCE_LEN;
+
+// Argon2id as OWASP recommends it: 19 MiB, 2 iterations, 1 lane
+const KDF: Kdf = Kdf { memory_kib: 19 * 1024, iterations: 2, lanes: 1 };
+// Settings read from a header are capped, so a crafted file can not make opening it take
+// gigabytes of memory or minutes of work
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 16;
+const MAX_LANES: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Kdf {
+    memory_kib: u32,
+    iterations: u32,
+    lanes: u32,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], kdf: Kdf) -> Result<Key, &'static str> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.lanes, Some(32))
+        .map_err(|_| "the encryption header is damaged")?;
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| "the key could not be derived from the passphrase")?;
+    Ok(key)
+}
+
+fn encrypt_with(plaintext: &[u8], passphrase: &str, kdf: Kdf) -> Result<Vec<u8>, &'static str> {
+    let salt = <[u8; SALT_LEN]>::generate();
+    let nonce = XNonce::generate();
+    let mut data = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+    data.extend_from_slice(MAGIC);
+    for number in [kdf.memory_kib, kdf.iterations, kdf.lanes] {
+        data.extend_from_slice(&number.to_le_bytes());
+    }
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt, kdf)?);
+    // The header is authenticated along with the text, so changing the settings or the salt is
+    // caught like any other tampering
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &data })
+        .map_err(|_| "encryption failed")?;
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+// A new salt and nonce every time, so the same text never encrypts to the same bytes twice
+fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, &'static str> {
+    encrypt_with(plaintext, passphrase, KDF)
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// A wrong passphrase and changed bytes look the same to the cipher, the tag just does not match.
+// The message becomes an AppError::Crypto naming the file in read_encrypted.
+fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, &'static str> {
+    if !is_encrypted(data) {
+        return Err("not an encrypted file");
+    }
+    if data.len() < HEADER_LEN + TAG_LEN {
+        return Err("the encrypted file is cut short");
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let number = |index: usize| {
+        let at = MAGIC.len() + index * 4;
+        u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+    };
+    let kdf = Kdf { memory_kib: number(0), iterations: number(1), lanes: number(2) };
+    if kdf.memory_kib > MAX_MEMORY_KIB || kdf.iterations > MAX_ITERATIONS || kdf.lanes > MAX_LANES {
+        return Err("the encryption header is damaged");
+    }
+    let salt = &header[MAGIC.len() + 12..MAGIC.len() + 12 + SALT_LEN];
+    let nonce = XNonce::try_from(&header[HEADER_LEN - NONCE_LEN..]).map_err(|_| "the encryption header is damaged")?;
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, salt, kdf)?);
+    cipher
+        .decrypt(&nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|_| "wrong passphrase, or the file was changed after it was encrypted")
+}
+
+// Only looks at the first bytes, so it is cheap to ask before deciding how to open a file
+pub fn is_encrypted_file(path: &str) -> Result<bool, AppError> {
+    let file = File::open(resolve(path)?).map_err(|e| AppError::io("open", path, e))?;
+    let mut start = Vec::new();
+    file.take(MAGIC.len() as u64)
+        .read_to_end(&mut start)
+        .map_err(|e| AppError::io("read", path, e))?;
+    Ok(is_encrypted(&start))
+}
+
+pub fn read_encrypted(path: &str, passphrase: &str) -> Result<Vec<u8>, AppError> {
+    let data = std::fs::read(resolve(path)?).map_err(|e| AppError::io("read", path, e))?;
+    decrypt(&data, passphrase).map_err(|message| AppError::crypto(path, message))
+}
+
+// Always atomic, a half written encrypted file could not be read back at all
+pub fn write_encrypted(path: &str, plaintext: &[u8], passphrase: &str, mode: WriteMode, backup: bool) -> Result<(), AppError> {
+    let data = encrypt(plaintext, passphrase).map_err(|message| AppError::crypto(path, message))?;
+    write_atomic(path, &data, mode, backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The smallest settings Argon2 accepts, the real ones take a while in a debug build
+    const FAST: Kdf = Kdf { memory_kib: 8, iterations: 1, lanes: 1 };
+
+    #[test]
+    fn round_trips_with_the_right_passphrase() {
+        let data = encrypt_with(b"user: me\npassword: hunter2\n", "correct horse", FAST).unwrap();
+        assert!(is_encrypted(&data));
+        assert!(!data.windows(7).any(|w| w == b"hunter2"));
+        assert_eq!(decrypt(&data, "correct horse").unwrap(), b"user: me\npassword: hunter2\n");
+        assert_ne!(data, encrypt_with(b"user: me\npassword: hunter2\n", "correct horse", FAST).unwrap());
+
+        let empty = encrypt_with(b"", "x", FAST).unwrap();
+        assert_eq!(decrypt(&empty, "x").unwrap(), b"");
+    }
+
+    #[test]
+    fn detects_wrong_passphrases_and_tampering() {
+        let data = encrypt_with(b"secret", "pass", FAST).unwrap();
+        let wrong = "wrong passphrase, or the file was changed after it was encrypted";
+        assert_eq!(decrypt(&data, "Pass").unwrap_err(), wrong);
+
+        // A flipped bit in the ciphertext, the salt or the memory setting
+        for at in [data.len() - 1, HEADER_LEN - NONCE_LEN - 1, MAGIC.len()] {
+            let mut changed = data.clone();
+            changed[at] ^= 1;
+            assert_eq!(decrypt(&changed, "pass").unwrap_err(), wrong);
+        }
+
+        let mut huge = data.clone();
+        huge[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(decrypt(&huge, "pass").unwrap_err(), "the encryption header is damaged");
+        assert_eq!(decrypt(&data[..HEADER_LEN + 3], "pass").unwrap_err(), "the encrypted file is cut short");
+        assert_eq!(decrypt(b"plain text", "pass").unwrap_err(), "not an encrypted file");
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+use crate::apperror::AppError;
+use super::read_to_string;
+
+// Tab for .tsv files, comma for everything else
+pub fn delimiter_for(path: &str) -> char {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("tsv") => '\t',
+        _ => ',',
+    }
+}
+
+pub fn read_csv(path: &str) -> Result<Vec<Vec<String>>, AppError> {
+    parse_csv(path, &read_to_string(path)?, delimiter_for(path))
+}
+
+// Splits CSV text into records (RFC 4180): fields separated by `delimiter`, optionally quoted,
+// "" inside quotes for a literal quote, and quoted fields may span lines. `name` only shows up
+// in errors.
+pub fn parse_csv(name: &str, text: &str, delimiter: char) -> Result<Vec<Vec<String>>, AppError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    // Where the open quote was, for the error when it is never closed
+    let mut quote_start = (0, 0);
+    let (mut line, mut column) = (1, 0);
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        column += 1;
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    column += 1;
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                '\n' => {
+                    line += 1;
+                    column = 0;
+                    field.push(c);
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => {
+                in_quotes = true;
+                quote_start = (line, column);
+            }
+            '"' => return Err(AppError::syntax(name, line, column, "quote in the middle of an unquoted field")),
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+                column = 0;
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(AppError::syntax(name, quote_start.0, quote_start.1, "quoted field is never closed"));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// One record as a CSV line without the newline, quoting only the fields that need it
+fn format_csv_record<S: AsRef<str>>(fields: &[S], delimiter: char) -> String {
+    let quoted: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([delimiter, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    quoted.join(&delimiter.to_string())
+}
+
+// Records as CSV text, one line per record, ready to be written to a file
+pub fn format_csv(records: &[Vec<String>], delimiter: char) -> String {
+    let mut text = String::new();
+    for record in records {
+        text.push_str(&format_csv_record(record, delimiter));
+        text.push('\n');
+    }
+    text
+}
+
+// Records as a table with every column as wide as its widest cell, the first record as the header:
+//     name  | age
+//     ------+----
+//     Alice | 30
+pub fn format_table(records: &[Vec<String>]) -> Vec<String> {
+    let columns = records.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for record in records {
+        for (width, field) in widths.iter_mut().zip(record) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+
+    let row = |record: &Vec<String>| {
+        let cells: Vec<String> = (0..columns)
+            .map(|i| {
+                // Line breaks inside a quoted field would tear the table apart
+                let cell = record.get(i).map_or(String::new(), |field| field.replace(['\r', '\n'], " "));
+                format!("{:<width$}", cell, width = widths[i])
+            })
+            .collect();
+        cells.join(" | ").trim_end().to_string()
+    };
+
+    let mut lines = Vec::with_capacity(records.len() + 1);
+    for (index, record) in records.iter().enumerate() {
+        lines.push(row(record));
+        if index == 0 {
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            lines.push(rule.join("-+-"));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quoted_fields() {
+        let text = "name,note\r\nAlice,\"says \"\"hi\"\", twice\"\nBob,\"two\nlines\"\n";
+        let records = parse_csv("t.csv", text, ',').unwrap();
+        assert_eq!(records, [
+            vec!["name", "note"],
+            vec!["Alice", "says \"hi\", twice"],
+            vec!["Bob", "two\nlines"],
+        ]);
+        assert_eq!(format_csv_record(&records[1], ','), "Alice,\"says \"\"hi\"\", twice\"");
+
+        let error = parse_csv("t.csv", "a,b\n\"open,c\n", ',').err().unwrap();
+        assert_eq!(error.to_string(), "t.csv:2:1: quoted field is never closed");
+    }
+
+    #[test]
+    fn aligns_tables() {
+        let records = vec![
+            vec![String::from("name"), String::from("age")],
+            vec![String::from("Alice"), String::from("30")],
+            vec![String::from("Bo")],
+        ];
+        assert_eq!(format_table(&records), ["name  | age", "------+----", "Alice | 30", "Bo    |"]);
+    }
+}
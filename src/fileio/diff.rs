@@ -0,0 +1,400 @@
+use std::fs;
+use crate::apperror::AppError;
+use super::{decode, detect_data_encoding, resolve, LineEnding};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffOptions {
+    // Unchanged lines shown around every change in unified output
+    pub context: usize,
+    // Two columns instead of unified output
+    pub side_by_side: bool,
+    // Width of each column in side-by-side output
+    pub width: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            context: 3,
+            side_by_side: false,
+            width: 38,
+        }
+    }
+}
+
+// One step of turning the old lines into the new ones, holding indices into both
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+// Above this many cells (64 MiB of u32s) the LCS table is not built, the middle is split with
+// Hirschberg's method instead, which needs two rows at a time. Both take quadratic time.
+const MAX_TABLE_CELLS: usize = 16 * 1024 * 1024;
+
+// Line diff based on the longest common subsequence. The common start and end are cut off first,
+// so the quadratic work only covers the part that actually changed.
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Same(i, i)).collect();
+    split_edits(old_mid, new_mid, (prefix, prefix), MAX_TABLE_CELLS, &mut edits);
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+    edits.extend((0..suffix).map(|k| Edit::Same(old_end + k, new_end + k)));
+    edits
+}
+
+// Hirschberg: the best place in `new` to pair with the middle of `old` splits the problem in two
+// halves that are solved the same way, until they fit in a table of `max_cells`. `at` is where
+// `old` and `new` start in the whole files.
+fn split_edits<T: PartialEq>(old: &[T], new: &[T], at: (usize, usize), max_cells: usize, edits: &mut Vec<Edit>) {
+    let (old_at, new_at) = at;
+    if old.len() < 2 || (old.len() + 1).saturating_mul(new.len() + 1) <= max_cells {
+        return table_edits(old, new, old_at, new_at, edits);
+    }
+    let mid = old.len() / 2;
+    let front = lcs_lengths(&old[..mid], new);
+    let back = lcs_lengths_from_end(&old[mid..], new);
+    let split = (0..=new.len()).max_by_key(|&j| (front[j] + back[j], std::cmp::Reverse(j))).unwrap_or(0);
+    split_edits(&old[..mid], &new[..split], at, max_cells, edits);
+    split_edits(&old[mid..], &new[split..], (old_at + mid, new_at + split), max_cells, edits);
+}
+
+// row[j] is the length of the common subsequence of `old` and new[..j]
+fn lcs_lengths<T: PartialEq>(old: &[T], new: &[T]) -> Vec<u32> {
+    let mut row = vec![0u32; new.len() + 1];
+    let mut next = row.clone();
+    for a in old {
+        for j in 1..=new.len() {
+            next[j] = if *a == new[j - 1] { row[j - 1] + 1 } else { row[j].max(next[j - 1]) };
+        }
+        std::mem::swap(&mut row, &mut next);
+    }
+    row
+}
+
+// row[j] is the length of the common subsequence of `old` and new[j..]
+fn lcs_lengths_from_end<T: PartialEq>(old: &[T], new: &[T]) -> Vec<u32> {
+    let mut row = vec![0u32; new.len() + 1];
+    let mut next = row.clone();
+    for a in old.iter().rev() {
+        for j in (0..new.len()).rev() {
+            next[j] = if *a == new[j] { row[j + 1] + 1 } else { row[j].max(next[j + 1]) };
+        }
+        std::mem::swap(&mut row, &mut next);
+    }
+    row
+}
+
+fn table_edits<T: PartialEq>(old: &[T], new: &[T], old_at: usize, new_at: usize, edits: &mut Vec<Edit>) {
+    // lcs[i][j] is the length of the common subsequence of old[i..] and new[j..]
+    let columns = new.len() + 1;
+    let mut lcs = vec![0u32; (old.len() + 1) * columns];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i * columns + j] = if old[i] == new[j] {
+                lcs[(i + 1) * columns + j + 1] + 1
+            } else {
+                lcs[(i + 1) * columns + j].max(lcs[i * columns + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push(Edit::Same(old_at + i, new_at + j));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[(i + 1) * columns + j] >= lcs[i * columns + j + 1]) {
+            // Removals go before additions, like diff(1) prints them
+            edits.push(Edit::Removed(old_at + i));
+            i += 1;
+        } else {
+            edits.push(Edit::Added(new_at + j));
+            j += 1;
+        }
+    }
+}
+
+// Unified format: "--- old", "+++ new", then "@@ -start,count +start,count @@" hunks with
+// `context` unchanged lines around the changes. Empty when nothing changed.
+pub fn unified(old_name: &str, new_name: &str, old: &[&str], new: &[&str], context: usize) -> Vec<String> {
+    let edits = diff_lines(old, new);
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&k| !matches!(edits[k], Edit::Same(..)))
+        .collect();
+    if changes.is_empty() {
+        return Vec::new();
+    }
+
+    // Where each edit sits in the old and new file, counted in lines before it
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in &edits {
+        positions.push((old_pos, new_pos));
+        match edit {
+            Edit::Same(..) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Removed(_) => old_pos += 1,
+            Edit::Added(_) => new_pos += 1,
+        }
+    }
+
+    let mut output = vec![format!("--- {}", old_name), format!("+++ {}", new_name)];
+    let mut k = 0;
+    while k < changes.len() {
+        // Changes whose context would touch or overlap end up in the same hunk
+        let mut last = k;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * context + 1 {
+            last += 1;
+        }
+        let start = changes[k].saturating_sub(context);
+        let end = (changes[last] + context + 1).min(edits.len());
+
+        let mut body = Vec::new();
+        let (mut old_count, mut new_count) = (0, 0);
+        for edit in &edits[start..end] {
+            match *edit {
+                Edit::Same(i, _) => {
+                    body.push(format!(" {}", old[i]));
+                    old_count += 1;
+                    new_count += 1;
+                }
+                Edit::Removed(i) => {
+                    body.push(format!("-{}", old[i]));
+                    old_count += 1;
+                }
+                Edit::Added(j) => {
+                    body.push(format!("+{}", new[j]));
+                    new_count += 1;
+                }
+            }
+        }
+        // An empty range is numbered after the line it follows, otherwise from its first line
+        let (old_pos, new_pos) = positions[start];
+        let old_start = if old_count == 0 { old_pos } else { old_pos + 1 };
+        let new_start = if new_count == 0 { new_pos } else { new_pos + 1 };
+        output.push(format!("@@ -{},{} +{},{} @@", old_start, old_count, new_start, new_count));
+        output.append(&mut body);
+        k = last + 1;
+    }
+    output
+}
+
+// Both files next to each other, like sdiff: ' ' unchanged, '|' changed, '<' only in the old
+// file, '>' only in the new one. Lines longer than `width` are cut off.
+pub fn side_by_side(old: &[&str], new: &[&str], width: usize) -> Vec<String> {
+    let edits = diff_lines(old, new);
+    let row = |left: &str, marker: char, right: &str| {
+        let left: String = left.chars().take(width).collect();
+        let right: String = right.chars().take(width).collect();
+        format!("{:<width$} {} {}", left, marker, right, width = width).trim_end().to_string()
+    };
+
+    let mut output = Vec::new();
+    let mut k = 0;
+    while k < edits.len() {
+        if let Edit::Same(i, j) = edits[k] {
+            output.push(row(old[i], ' ', new[j]));
+            k += 1;
+            continue;
+        }
+        // A run of removals followed by additions is shown as changed lines, pairwise
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        while let Some(Edit::Removed(i)) = edits.get(k) {
+            removed.push(old[*i]);
+            k += 1;
+        }
+        while let Some(Edit::Added(j)) = edits.get(k) {
+            added.push(new[*j]);
+            k += 1;
+        }
+        for n in 0..removed.len().max(added.len()) {
+            match (removed.get(n), added.get(n)) {
+                (Some(left), Some(right)) => output.push(row(left, '|', right)),
+                (Some(left), None) => output.push(row(left, '<', "")),
+                (None, Some(right)) => output.push(row("", '>', right)),
+                (None, None) => {}
+            }
+        }
+    }
+    output
+}
+
+// Compares two files and reports the output to `on_line`, which returns false to stop.
+// Returns whether the files differ.
+pub fn diff<F>(old_path: &str, new_path: &str, options: DiffOptions, mut on_line: F) -> Result<bool, AppError>
+where
+    F: FnMut(&str) -> bool,
+{
+    let old_bytes = fs::read(resolve(old_path)?).map_err(|e| AppError::io("read", old_path, e))?;
+    let new_bytes = fs::read(resolve(new_path)?).map_err(|e| AppError::io("read", new_path, e))?;
+    if old_bytes == new_bytes {
+        return Ok(false);
+    }
+    // Detected like read and convert do, so UTF-16 and Latin-1 files compare as text
+    let (old_encoding, new_encoding) = match (detect_data_encoding(&old_bytes), detect_data_encoding(&new_bytes)) {
+        (Some(old), Some(new)) => (old, new),
+        _ => {
+            on_line(&format!("Binary files {} and {} differ", old_path, new_path));
+            return Ok(true);
+        }
+    };
+
+    let old_text = decode(&old_bytes, old_encoding);
+    let new_text = decode(&new_bytes, new_encoding);
+    let old: Vec<&str> = old_text.lines().collect();
+    let new: Vec<&str> = new_text.lines().collect();
+    let output = if options.side_by_side {
+        side_by_side(&old, &new, options.width.max(1))
+    } else {
+        unified(old_path, new_path, &old, &new, options.context)
+    };
+    for line in &output {
+        if !on_line(line) {
+            break;
+        }
+    }
+    // The bytes differ but the lines may not, e.g. only the encoding or the line endings changed
+    if output.is_empty() {
+        let mut differences = Vec::new();
+        if old_encoding != new_encoding {
+            differences.push(format!("encoding ({} and {})", old_encoding.name(), new_encoding.name()));
+        }
+        if LineEnding::detect(&old_text) != LineEnding::detect(&new_text) {
+            differences.push(String::from("line endings"));
+        }
+        if old_text.ends_with('\n') != new_text.ends_with('\n') {
+            differences.push(String::from("the newline at the end"));
+        }
+        // Mixed line endings can change without the most common one changing
+        if differences.is_empty() {
+            differences.push(String::from("line endings"));
+        }
+        on_line(&format!("Files differ only in {}", differences.join(" and ")));
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_a_minimal_diff() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "c", "d", "e"];
+        assert_eq!(
+            diff_lines(&old, &new),
+            [Edit::Same(0, 0), Edit::Removed(1), Edit::Same(2, 1), Edit::Same(3, 2), Edit::Added(3)]
+        );
+        assert_eq!(diff_lines::<&str>(&[], &["x"]), [Edit::Added(0)]);
+    }
+
+    #[test]
+    fn splits_large_diffs_without_the_full_table() {
+        let old: Vec<u32> = (0..60).map(|n| n * 7 % 11).collect();
+        let new: Vec<u32> = (0..50).map(|n| n * 5 % 13).collect();
+        let same = |edits: &[Edit]| edits.iter().filter(|edit| matches!(edit, Edit::Same(..))).count();
+        let mut table = Vec::new();
+        table_edits(&old, &new, 0, 0, &mut table);
+        let mut split = Vec::new();
+        split_edits(&old, &new, (0, 0), 16, &mut split);
+
+        // Just as long a common subsequence, and the edits still turn old into new
+        assert_eq!(same(&split), same(&table));
+        let rebuilt: Vec<u32> = split
+            .iter()
+            .filter_map(|edit| match *edit {
+                Edit::Same(i, _) => Some(old[i]),
+                Edit::Added(j) => Some(new[j]),
+                Edit::Removed(_) => None,
+            })
+            .collect();
+        assert_eq!(rebuilt, new);
+        assert_eq!(split.len() - same(&split), old.len() + new.len() - 2 * same(&table));
+    }
+
+    #[test]
+    fn prints_unified_hunks() {
+        let old: Vec<String> = (1..=12).map(|n| n.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = String::from("two");
+        new.remove(10);
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+
+        let expected = [
+            "--- a", "+++ b",
+            "@@ -1,3 +1,3 @@", " 1", "-2", "+two", " 3",
+            "@@ -10,3 +10,2 @@", " 10", "-11", " 12",
+        ];
+        assert_eq!(unified("a", "b", &old, &new, 1), expected);
+        // With more context the two changes share one hunk
+        assert_eq!(unified("a", "b", &old, &new, 4)[2], "@@ -1,12 +1,11 @@");
+        assert!(unified("a", "b", &old, &old, 3).is_empty());
+        assert_eq!(unified("a", "b", &[], &["new"], 3)[2], "@@ -0,0 +1,1 @@");
+    }
+
+    #[test]
+    fn renders_side_by_side() {
+        let old = ["same", "old", "gone"];
+        let new = ["same", "new"];
+        assert_eq!(side_by_side(&old, &new, 6), ["same     same", "old    | new", "gone   <"]);
+    }
+
+    #[test]
+    fn compares_other_encodings_as_text() {
+        let dir = crate::fileio::test_dir("diff-encodings");
+        let (old, new) = (dir.join("old.txt"), dir.join("new.txt"));
+        fs::write(&old, b"caf\xE9\nsame\n").unwrap();
+        let wide: Vec<u8> = [0xFF, 0xFE].into_iter().chain("th\u{e9}\nsame\n".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        fs::write(&new, wide).unwrap();
+        let mut lines = Vec::new();
+        let differ = diff(old.to_str().unwrap(), new.to_str().unwrap(), DiffOptions::default(), |line| {
+            lines.push(line.to_string());
+            true
+        })
+        .unwrap();
+        assert!(differ);
+        assert!(lines.contains(&String::from("-caf\u{e9}")), "{:?}", lines);
+        assert!(lines.contains(&String::from("+th\u{e9}")), "{:?}", lines);
+
+        // The same text, told apart only by how it is stored
+        let only = |old_bytes: &[u8], new_bytes: Vec<u8>| {
+            fs::write(&old, old_bytes).unwrap();
+            fs::write(&new, new_bytes).unwrap();
+            let mut lines = Vec::new();
+            diff(old.to_str().unwrap(), new.to_str().unwrap(), DiffOptions::default(), |line| {
+                lines.push(line.to_string());
+                true
+            })
+            .unwrap();
+            lines
+        };
+        let utf16 = |text: &str| [0xFF, 0xFE].into_iter().chain(text.encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        assert_eq!(only(b"a\nb\n", utf16("a\nb\n")), ["Files differ only in encoding (UTF-8 and UTF-16LE)"]);
+        assert_eq!(only(b"a\nb\n", b"a\r\nb\r\n".to_vec()), ["Files differ only in line endings"]);
+        assert_eq!(
+            only(b"a\nb\n", utf16("a\r\nb\r\n")),
+            ["Files differ only in encoding (UTF-8 and UTF-16LE) and line endings"]
+        );
+        assert_eq!(only(b"a\nb\n", b"a\nb".to_vec()), ["Files differ only in the newline at the end"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
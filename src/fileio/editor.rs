@@ -0,0 +1,413 @@
+use std::io::{self, BufRead, Write};
+use crate::apperror::AppError;
+use super::{convert_line_endings, encode, read_decoded, read_until_sentinel, write_atomic, Encoding, LineEnding, WriteMode, SENTINEL};
+
+// How many changes `u` can take back
+const UNDO_LIMIT: usize = 100;
+
+const HELP: &str = "\
+Commands take an optional range: N, N,M, . (current line), $ (last line) or , (whole file)
+  p   print            n   print with line numbers
+  a   append after     i   insert before        (type lines, end with a single '.')
+  c   change lines     d   delete
+  s/old/new/[g]        substitute text, g replaces every match on a line
+  /text  ?text         search forward / backward
+  N   go to line N     u   undo the last change
+  w [path]  write      q   quit (warns about unsaved changes)   Q   quit without saving
+  h   this help";
+
+// A line-oriented editor in the spirit of ed, working on an in-memory copy of a file.
+// Line numbers are 1-based, `current` is 0 only while the buffer is empty.
+pub struct Editor {
+    path: String,
+    lines: Vec<String>,
+    current: usize,
+    dirty: bool,
+    quit_warned: bool,
+    undo: Vec<(Vec<String>, usize)>,
+    // The lines as they are on disk, so undoing back to them is not an unsaved change
+    saved: Vec<String>,
+    // Written back the way the file came, so editing one line does not convert the whole file
+    encoding: Encoding,
+    ending: LineEnding,
+    final_newline: bool,
+}
+
+impl Editor {
+    // A file that does not exist yet opens as an empty buffer and is created on `w`, as UTF-8
+    // with LF line endings
+    pub fn open(path: &str) -> Result<Editor, AppError> {
+        let (text, encoding) = if super::exists(path) {
+            match read_decoded(path)? {
+                (text, Some(encoding)) => (text, encoding),
+                (_, None) => {
+                    let e = io::Error::new(io::ErrorKind::InvalidData, "the file looks binary, not text");
+                    return Err(AppError::io("edit", path, e));
+                }
+            }
+        } else {
+            (String::new(), Encoding::Utf8)
+        };
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        Ok(Editor {
+            path: path.to_string(),
+            current: lines.len(),
+            saved: lines.clone(),
+            lines,
+            dirty: false,
+            quit_warned: false,
+            undo: Vec::new(),
+            encoding,
+            ending: LineEnding::detect(&text).unwrap_or(LineEnding::Lf),
+            final_newline: text.is_empty() || text.ends_with('\n'),
+        })
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // Runs one command. Text for a, i and c is read from `input`, everything else goes to `out`.
+    // Ok(false) means the editor should close, Err is a message for the user like ed's "?".
+    pub fn execute<R: BufRead, W: Write>(
+        &mut self,
+        command: &str,
+        input: &mut R,
+        out: &mut W,
+    ) -> Result<bool, String> {
+        let command = command.trim();
+        if command != "q" {
+            self.quit_warned = false;
+        }
+
+        if let Some(text) = command.strip_prefix('/') {
+            return self.search(text, true, out).map(|_| true);
+        }
+        if let Some(text) = command.strip_prefix('?') {
+            return self.search(text, false, out).map(|_| true);
+        }
+
+        let split = command
+            .find(|c: char| !(c.is_ascii_digit() || c == ',' || c == '.' || c == '$'))
+            .unwrap_or(command.len());
+        let (range_text, rest) = command.split_at(split);
+        let range = self.parse_range(range_text)?;
+        let mut chars = rest.chars();
+        let letter = chars.next();
+        let argument = chars.as_str();
+
+        match letter {
+            // A bare address moves there and prints the line, an empty command prints the next one
+            None => {
+                let line = match range {
+                    Some((_, end)) => end,
+                    None => self.current + 1,
+                };
+                self.check_line(line)?;
+                self.current = line;
+                say(out, &self.lines[line - 1])?;
+            }
+            Some('p') | Some('n') => {
+                let (start, end) = self.lines_or_current(range)?;
+                for number in start..=end {
+                    if letter == Some('n') {
+                        say(out, &format!("{}\t{}", number, self.lines[number - 1]))?;
+                    } else {
+                        say(out, &self.lines[number - 1])?;
+                    }
+                }
+                self.current = end;
+            }
+            Some('a') | Some('i') => {
+                // 0a appends before the first line, which is also the only address an empty buffer has
+                let at = match range {
+                    Some((_, end)) => end,
+                    None => self.current,
+                };
+                if at > self.lines.len() || (letter == Some('i') && at == 0 && !self.lines.is_empty()) {
+                    return Err(format!("no line {}", at));
+                }
+                let index = if letter == Some('a') { at } else { at.saturating_sub(1) };
+                let text = read_text(input)?;
+                self.remember();
+                let count = text.len();
+                self.lines.splice(index..index, text);
+                self.current = index + count;
+                self.changed();
+            }
+            Some('c') => {
+                let (start, end) = self.lines_or_current(range)?;
+                let text = read_text(input)?;
+                self.remember();
+                let count = text.len();
+                self.lines.splice(start - 1..end, text);
+                self.current = (start - 1 + count).max(1).min(self.lines.len());
+                self.changed();
+            }
+            Some('d') => {
+                let (start, end) = self.lines_or_current(range)?;
+                self.remember();
+                self.lines.drain(start - 1..end);
+                self.current = start.min(self.lines.len());
+                self.changed();
+            }
+            Some('s') => {
+                let (start, end) = self.lines_or_current(range)?;
+                let (old, new, global) = parse_substitution(argument)?;
+                let before = self.lines.clone();
+                let mut last_changed = None;
+                for number in start..=end {
+                    let line = &mut self.lines[number - 1];
+                    if line.contains(old.as_str()) {
+                        *line = if global { line.replace(&old, &new) } else { line.replacen(&old, &new, 1) };
+                        last_changed = Some(number);
+                    }
+                }
+                match last_changed {
+                    Some(number) => {
+                        self.push_undo(before);
+                        self.current = number;
+                        self.changed();
+                        say(out, &self.lines[number - 1])?;
+                    }
+                    None => return Err(format!("'{}' not found", old)),
+                }
+            }
+            Some('u') => match self.undo.pop() {
+                Some((lines, current)) => {
+                    self.lines = lines;
+                    self.current = current;
+                    self.dirty = self.lines != self.saved;
+                }
+                None => return Err(String::from("nothing to undo")),
+            },
+            Some('w') => {
+                let argument = argument.trim();
+                if !argument.is_empty() {
+                    self.path = argument.to_string();
+                }
+                let mut text = self.lines.join("\n");
+                if self.final_newline && !self.lines.is_empty() {
+                    text.push('\n');
+                }
+                let bytes = encode(&convert_line_endings(&text, self.ending), self.encoding).map_err(|e| e.to_string())?;
+                write_atomic(&self.path, &bytes, WriteMode::Overwrite, false).map_err(|e| e.to_string())?;
+                self.dirty = false;
+                self.saved = self.lines.clone();
+                say(out, &format!("{} bytes written to {}", bytes.len(), self.path))?;
+            }
+            Some('q') => {
+                if self.dirty && !self.quit_warned {
+                    self.quit_warned = true;
+                    return Err(String::from("unsaved changes, 'w' to save, 'q' again or 'Q' to discard them"));
+                }
+                return Ok(false);
+            }
+            Some('Q') => return Ok(false),
+            Some('h') => say(out, HELP)?,
+            Some(other) => return Err(format!("unknown command '{}', 'h' for help", other)),
+        }
+        Ok(true)
+    }
+
+    fn parse_address(&self, text: &str) -> Result<usize, String> {
+        match text {
+            "." => Ok(self.current),
+            "$" => Ok(self.lines.len()),
+            _ => text.parse().map_err(|_| format!("bad address '{}'", text)),
+        }
+    }
+
+    fn parse_range(&self, text: &str) -> Result<Option<(usize, usize)>, String> {
+        if text.is_empty() {
+            return Ok(None);
+        }
+        if text == "," {
+            return Ok(Some((1, self.lines.len())));
+        }
+        let (start, end) = match text.split_once(',') {
+            Some((start, end)) => (self.parse_address(start)?, self.parse_address(end)?),
+            None => {
+                let line = self.parse_address(text)?;
+                (line, line)
+            }
+        };
+        if start > end {
+            return Err(String::from("range is backwards"));
+        }
+        Ok(Some((start, end)))
+    }
+
+    fn check_line(&self, line: usize) -> Result<(), String> {
+        if line == 0 || line > self.lines.len() {
+            return Err(format!("no line {}", line));
+        }
+        Ok(())
+    }
+
+    fn lines_or_current(&self, range: Option<(usize, usize)>) -> Result<(usize, usize), String> {
+        let (start, end) = range.unwrap_or((self.current, self.current));
+        self.check_line(start)?;
+        self.check_line(end)?;
+        Ok((start, end))
+    }
+
+    fn search<W: Write>(&mut self, text: &str, forward: bool, out: &mut W) -> Result<(), String> {
+        if text.is_empty() || self.lines.is_empty() {
+            return Err(String::from("nothing to search for"));
+        }
+        let len = self.lines.len();
+        // Start after (or before) the current line and wrap around, like ed
+        for step in 1..=len {
+            let index = if forward {
+                (self.current + step - 1) % len
+            } else {
+                (self.current + len - 1 + len - step) % len
+            };
+            if self.lines[index].contains(text) {
+                self.current = index + 1;
+                return say(out, &format!("{}\t{}", self.current, self.lines[index]));
+            }
+        }
+        Err(format!("'{}' not found", text))
+    }
+
+    fn remember(&mut self) {
+        let snapshot = self.lines.clone();
+        self.push_undo(snapshot);
+    }
+
+    fn push_undo(&mut self, lines: Vec<String>) {
+        if self.undo.len() == UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.undo.push((lines, self.current));
+    }
+
+    fn changed(&mut self) {
+        self.dirty = true;
+    }
+}
+
+fn say<W: Write>(out: &mut W, text: &str) -> Result<(), String> {
+    writeln!(out, "{}", text).map_err(|e| e.to_string())
+}
+
+fn read_text<R: BufRead>(input: &mut R) -> Result<Vec<String>, String> {
+    let text = read_until_sentinel(input, SENTINEL).map_err(|e| e.to_string())?;
+    Ok(text.lines().map(String::from).collect())
+}
+
+// s/old/new/ or s/old/new/g, any character right after the s works as the delimiter
+fn parse_substitution(argument: &str) -> Result<(String, String, bool), String> {
+    let mut chars = argument.chars();
+    let delimiter = chars.next().ok_or("expected s/old/new/")?;
+    let parts: Vec<&str> = chars.as_str().split(delimiter).collect();
+    match parts.as_slice() {
+        [old, new] | [old, new, ""] if !old.is_empty() => Ok((old.to_string(), new.to_string(), false)),
+        [old, new, "g"] if !old.is_empty() => Ok((old.to_string(), new.to_string(), true)),
+        _ => Err(String::from("expected s/old/new/ or s/old/new/g")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::fileio::test_dir;
+
+    fn run(editor: &mut Editor, command: &str, input: &str) -> Result<String, String> {
+        let mut out = Vec::new();
+        editor.execute(command, &mut input.as_bytes(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn edits_and_writes_a_file() {
+        let dir = test_dir("editor");
+        let path = dir.join("notes.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "alpha\nbeta\ngamma\n").unwrap();
+
+        let mut editor = Editor::open(path).unwrap();
+        assert_eq!(run(&mut editor, "2", "").unwrap(), "beta\n");
+        run(&mut editor, "a", "inserted\n.\n").unwrap();
+        run(&mut editor, "1d", "").unwrap();
+        assert_eq!(run(&mut editor, ",s/a/A/g", "").unwrap(), "gAmmA\n");
+        run(&mut editor, "2c", "changed\n.\n").unwrap();
+        assert_eq!(run(&mut editor, ",n", "").unwrap(), "1\tbetA\n2\tchanged\n3\tgAmmA\n");
+        assert!(editor.is_dirty());
+
+        run(&mut editor, "w", "").unwrap();
+        assert!(!editor.is_dirty());
+        assert_eq!(fs::read_to_string(path).unwrap(), "betA\nchanged\ngAmmA\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undoes_changes_and_warns_before_quitting() {
+        let dir = test_dir("editor-undo");
+        let path = dir.join("new.txt");
+        let mut editor = Editor::open(path.to_str().unwrap()).unwrap();
+
+        run(&mut editor, "a", "one\ntwo\n.\n").unwrap();
+        run(&mut editor, "1d", "").unwrap();
+        assert_eq!(editor.lines(), ["two"]);
+        run(&mut editor, "u", "").unwrap();
+        assert_eq!(editor.lines(), ["one", "two"]);
+
+        // Undoing everything since the last write leaves nothing to save
+        run(&mut editor, "w", "").unwrap();
+        run(&mut editor, "1d", "").unwrap();
+        assert!(editor.is_dirty());
+        run(&mut editor, "u", "").unwrap();
+        assert!(!editor.is_dirty());
+
+        run(&mut editor, "1d", "").unwrap();
+        assert!(run(&mut editor, "q", "").is_err());
+        let mut out = Vec::new();
+        assert_eq!(editor.execute("q", &mut "".as_bytes(), &mut out), Ok(false));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_line_endings_and_encoding() {
+        let dir = test_dir("editor-crlf");
+        let path = dir.join("windows.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "one\r\ntwo\r\nthree").unwrap();
+        let mut editor = Editor::open(path).unwrap();
+        run(&mut editor, "2c", "2\n.\n").unwrap();
+        run(&mut editor, "w", "").unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "one\r\n2\r\nthree");
+
+        fs::write(path, encode("caf\u{e9}\n", Encoding::Utf16Le).unwrap()).unwrap();
+        let mut editor = Editor::open(path).unwrap();
+        assert_eq!(editor.lines(), ["caf\u{e9}"]);
+        run(&mut editor, "a", "th\u{e9}\n.\n").unwrap();
+        run(&mut editor, "w", "").unwrap();
+        assert_eq!(read_decoded(path).unwrap(), (String::from("caf\u{e9}\nth\u{e9}\n"), Some(Encoding::Utf16Le)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn searches_and_rejects_bad_addresses() {
+        let dir = test_dir("editor-search");
+        let path = dir.join("list.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "apple\nbanana\ncherry\n").unwrap();
+
+        let mut editor = Editor::open(path).unwrap();
+        assert_eq!(run(&mut editor, "/an", "").unwrap(), "2\tbanana\n");
+        assert_eq!(run(&mut editor, "?pp", "").unwrap(), "1\tapple\n");
+        assert!(run(&mut editor, "7p", "").is_err());
+        assert!(run(&mut editor, "3,1p", "").is_err());
+        assert!(run(&mut editor, "/kiwi", "").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use std::fs::{self, File};
+use std::io::Read;
+use crate::apperror::AppError;
+
+// How much of a file is looked at to guess its encoding
+const SNIFF_LEN: u64 = 8192;
+
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: [u8; 2] = [0xFF, 0xFE];
+const UTF16BE_BOM: [u8; 2] = [0xFE, 0xFF];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    // UTF-8 starting with a byte order mark, as Windows tools like to write it
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    // ISO-8859-1, every byte is the character with the same number
+    Latin1,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "utf8" => Some(Encoding::Utf8),
+            "utf8bom" => Some(Encoding::Utf8Bom),
+            "utf16le" | "utf16" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "iso88591" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 with BOM",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn parse(name: &str) -> Option<LineEnding> {
+        match name.to_ascii_lowercase().as_str() {
+            "lf" | "unix" => Some(LineEnding::Lf),
+            "crlf" | "windows" => Some(LineEnding::CrLf),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    // Whichever style most lines use, None when there are no line breaks at all
+    pub fn detect(text: &str) -> Option<LineEnding> {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        match (lf, crlf) {
+            (0, 0) => None,
+            (lf, crlf) if crlf > lf => Some(LineEnding::CrLf),
+            _ => Some(LineEnding::Lf),
+        }
+    }
+}
+
+// Guesses the encoding from the start of a file. A byte order mark decides, then valid UTF-8,
+// then the zero bytes that UTF-16 puts next to every ASCII character. Anything else with NUL or
+// lots of control bytes is binary (None), and the rest is taken as Latin-1.
+pub fn detect_encoding(sample: &[u8]) -> Option<Encoding> {
+    if sample.starts_with(&UTF8_BOM) {
+        return Some(Encoding::Utf8Bom);
+    }
+    if sample.starts_with(&UTF16LE_BOM) {
+        return Some(Encoding::Utf16Le);
+    }
+    if sample.starts_with(&UTF16BE_BOM) {
+        return Some(Encoding::Utf16Be);
+    }
+
+    let zeros_at = |parity: usize| sample.iter().skip(parity).step_by(2).filter(|b| **b == 0).count();
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    let half = sample.len() / 2;
+    if half > 0 && odd * 2 > half && even * 10 < half {
+        return Some(Encoding::Utf16Le);
+    }
+    if half > 0 && even * 2 > half && odd * 10 < half {
+        return Some(Encoding::Utf16Be);
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) if !sample.contains(&0) => return Some(Encoding::Utf8),
+        // error_len() is None when the sample merely ends in the middle of a character
+        Err(e) if e.error_len().is_none() && !sample.contains(&0) => return Some(Encoding::Utf8),
+        _ => {}
+    }
+    let control = sample
+        .iter()
+        .filter(|b| (**b < 0x20 && !b"\t\n\r\x0c".contains(b)) || **b == 0x7F)
+        .count();
+    if sample.contains(&0) || control * 10 > sample.len() {
+        None
+    } else {
+        Some(Encoding::Latin1)
+    }
+}
+
+fn read_sample(path: &str) -> Result<Vec<u8>, AppError> {
+    let file = File::open(super::resolve(path)?).map_err(|e| AppError::io("open", path, e))?;
+    let mut sample = Vec::new();
+    file.take(SNIFF_LEN)
+        .read_to_end(&mut sample)
+        .map_err(|e| AppError::io("read", path, e))?;
+    Ok(sample)
+}
+
+pub fn detect_file_encoding(path: &str) -> Result<Option<Encoding>, AppError> {
+    read_sample(path).map(|sample| detect_encoding(&sample))
+}
+
+// The encoding and the line endings of a file, both from its start only, so this stays cheap
+// for a large log that is appended to
+pub fn detect_file_layout(path: &str) -> Result<(Option<Encoding>, Option<LineEnding>), AppError> {
+    let sample = read_sample(path)?;
+    let encoding = detect_encoding(&sample);
+    let ending = encoding.and_then(|encoding| LineEnding::detect(&decode(&sample, encoding)));
+    Ok((encoding, ending))
+}
+
+// detect_file_encoding for a file that is already in memory, looking at the same amount of it
+pub fn detect_data_encoding(data: &[u8]) -> Option<Encoding> {
+    detect_encoding(&data[..data.len().min(SNIFF_LEN as usize)])
+}
+
+// Byte order marks are dropped, invalid sequences become U+FFFD
+pub fn decode(bytes: &[u8], encoding: Encoding) -> String {
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => {
+            String::from_utf8_lossy(bytes.strip_prefix(&UTF8_BOM).unwrap_or(bytes)).into_owned()
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let big_endian = encoding == Encoding::Utf16Be;
+            let bom = if big_endian { UTF16BE_BOM } else { UTF16LE_BOM };
+            let units = bytes.strip_prefix(&bom).unwrap_or(bytes).chunks(2).map(|pair| match pair {
+                [a, b] if big_endian => u16::from_be_bytes([*a, *b]),
+                [a, b] => u16::from_le_bytes([*a, *b]),
+                // A lone byte at the end can not be a character
+                _ => 0xFFFD,
+            });
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        Encoding::Latin1 => bytes.iter().map(|byte| *byte as char).collect(),
+    }
+}
+
+// UTF-16 and UTF-8 with BOM get their byte order mark. Latin-1 can only hold the first 256
+// characters, the error names the first one that does not fit.
+pub fn encode(text: &str, encoding: Encoding) -> Result<Vec<u8>, AppError> {
+    match encoding {
+        Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        Encoding::Utf8Bom => Ok([&UTF8_BOM, text.as_bytes()].concat()),
+        Encoding::Utf16Le => Ok(UTF16LE_BOM
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect()),
+        Encoding::Utf16Be => Ok(UTF16BE_BOM
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect()),
+        Encoding::Latin1 => text
+            .chars()
+            .map(|c| {
+                u8::try_from(c).map_err(|_| AppError::invalid("text", format!("'{}' can not be written in Latin-1", c)))
+            })
+            .collect(),
+    }
+}
+
+// Every line break in `text` as `ending`, whatever it was before
+pub fn convert_line_endings(text: &str, ending: LineEnding) -> String {
+    let unix = text.replace("\r\n", "\n");
+    match ending {
+        LineEnding::Lf => unix,
+        LineEnding::CrLf => unix.replace('\n', "\r\n"),
+    }
+}
+
+// A whole file decoded to text, with the encoding it was detected as. Binary files (None) are
+// decoded as Latin-1 so they can still be shown, anything that writes the text back has to
+// refuse them.
+pub fn read_decoded(path: &str) -> Result<(String, Option<Encoding>), AppError> {
+    let bytes = fs::read(super::resolve(path)?).map_err(|e| AppError::io("read", path, e))?;
+    let encoding = detect_data_encoding(&bytes);
+    Ok((decode(&bytes, encoding.unwrap_or(Encoding::Latin1)), encoding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_encodings() {
+        assert_eq!(detect_encoding(b"plain ascii\n"), Some(Encoding::Utf8));
+        assert_eq!(detect_encoding("caf\u{e9}".as_bytes()), Some(Encoding::Utf8));
+        assert_eq!(detect_encoding(b"\xEF\xBB\xBFhi"), Some(Encoding::Utf8Bom));
+        assert_eq!(detect_encoding(b"\xFF\xFEh\0i\0"), Some(Encoding::Utf16Le));
+        assert_eq!(detect_encoding(b"\0h\0i\0\n"), Some(Encoding::Utf16Be));
+        assert_eq!(detect_encoding(b"h\0i\0\n\0"), Some(Encoding::Utf16Le));
+        assert_eq!(detect_encoding(b"caf\xE9 cr\xE8me\n"), Some(Encoding::Latin1));
+        assert_eq!(detect_encoding(&[0x7F, 0x45, 0x4C, 0x46, 2, 1, 1, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn round_trips_every_encoding() {
+        let text = "caf\u{e9}\r\nna\u{ef}ve\n";
+        for encoding in [Encoding::Utf8, Encoding::Utf8Bom, Encoding::Utf16Le, Encoding::Utf16Be, Encoding::Latin1] {
+            let bytes = encode(text, encoding).unwrap();
+            assert_eq!(detect_encoding(&bytes), Some(encoding), "{}", encoding.name());
+            assert_eq!(decode(&bytes, encoding), text);
+        }
+        assert_eq!(
+            encode("5 \u{20ac}", Encoding::Latin1).unwrap_err().to_string(),
+            "Invalid text: '\u{20ac}' can not be written in Latin-1"
+        );
+    }
+
+    #[test]
+    fn detects_and_converts_line_endings() {
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), Some(LineEnding::CrLf));
+        assert_eq!(LineEnding::detect("a\nb\n"), Some(LineEnding::Lf));
+        assert_eq!(LineEnding::detect("single line"), None);
+        assert_eq!(convert_line_endings("a\r\nb\nc", LineEnding::CrLf), "a\r\nb\r\nc");
+        assert_eq!(convert_line_endings("a\r\nb\n", LineEnding::Lf), "a\nb\n");
+    }
+
+    #[test]
+    fn reports_binary_files_as_undetected() {
+        let dir = crate::fileio::test_dir("encoding-binary");
+        let path = dir.join("image.bin");
+        std::fs::write(&path, [0x89, b'P', b'N', b'G', 0, 0, 0, 0x0D, 0x0A, 0x1A]).unwrap();
+        let (text, encoding) = read_decoded(path.to_str().unwrap()).unwrap();
+        assert_eq!(encoding, None);
+        assert_eq!(text.chars().count(), 10);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
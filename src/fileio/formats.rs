@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use crate::apperror::AppError;
+use super::{format_table, parse_csv, read_to_string};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Json,
+    Toml,
+    Text,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "tsv" => Some(Format::Tsv),
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "text" | "txt" => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "CSV",
+            Format::Tsv => "TSV",
+            Format::Json => "JSON",
+            Format::Toml => "TOML",
+            Format::Text => "text",
+        }
+    }
+}
+
+// How much of a file without a known extension is looked at to guess its format
+const SNIFF_LEN: u64 = 64 * 1024;
+
+pub fn detect_file_format(path: &str) -> Result<Format, AppError> {
+    let file = File::open(super::resolve(path)?).map_err(|e| AppError::io("open", path, e))?;
+    let mut sample = Vec::new();
+    file.take(SNIFF_LEN)
+        .read_to_end(&mut sample)
+        .map_err(|e| AppError::io("read", path, e))?;
+    Ok(detect_format(path, &String::from_utf8_lossy(&sample)))
+}
+
+// The extension decides when there is a known one, otherwise the start of the contents is looked at.
+// Only a guess, `sample` may be cut off anywhere, so a broken file is still reported by render.
+pub fn detect_format(path: &str, sample: &str) -> Format {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+    if let Some(format) = Format::parse(extension) {
+        return format;
+    }
+
+    // A TOML section like [server] also starts with a bracket
+    let is_key = |key: &str| !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || "_-.\"".contains(c));
+    let is_section = |line: &str| {
+        let line = line.trim();
+        line.strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .is_some_and(|name| is_key(name.trim_matches(['[', ']']).trim()))
+    };
+    let trimmed = sample.trim_start();
+    let first_line = trimmed.lines().next().unwrap_or("");
+    if trimmed.starts_with('{') || (trimmed.starts_with('[') && !is_section(first_line)) {
+        return Format::Json;
+    }
+
+    let lines: Vec<&str> = sample
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .take(10)
+        .collect();
+    if lines.is_empty() {
+        return Format::Text;
+    }
+    let is_assignment = |line: &str| line.split_once('=').is_some_and(|(key, _)| is_key(key.trim()));
+    if lines.iter().all(|line| is_section(line) || is_assignment(line)) && lines.iter().any(|line| is_assignment(line)) {
+        return Format::Toml;
+    }
+    // Several lines with the same number of separators look like a table
+    for (format, delimiter) in [(Format::Tsv, '\t'), (Format::Csv, ',')] {
+        let count = lines[0].matches(delimiter).count();
+        if lines.len() > 1 && count > 0 && lines.iter().all(|line| line.matches(delimiter).count() == count) {
+            return format;
+        }
+    }
+    Format::Text
+}
+
+// Reads a whole file and renders it for reading: CSV/TSV as an aligned table, JSON pretty-printed,
+// TOML parsed and printed back in a normalised layout, text as it is. Syntax errors come back as
+// AppError::Syntax with the line and column of the problem.
+pub fn render<F>(path: &str, format: Format, mut on_line: F) -> Result<(), AppError>
+where
+    F: FnMut(&str) -> bool,
+{
+    let contents = read_to_string(path)?;
+    let rendered = render_str(path, &contents, format)?;
+    for line in rendered.lines() {
+        if !on_line(line) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn render_str(name: &str, contents: &str, format: Format) -> Result<String, AppError> {
+    match format {
+        Format::Csv | Format::Tsv => {
+            let delimiter = if format == Format::Tsv { '\t' } else { ',' };
+            let records = parse_csv(name, contents, delimiter)?;
+            Ok(format_table(&records).join("\n"))
+        }
+        Format::Json => {
+            let value: serde_json::Value = serde_json::from_str(contents).map_err(|e| {
+                // serde_json appends " at line N column M" to its messages, that is in the position already
+                let message = e.to_string();
+                let message = message.split(" at line ").next().unwrap_or(&message).to_string();
+                AppError::syntax(name, e.line(), e.column(), message)
+            })?;
+            serde_json::to_string_pretty(&value).map_err(|e| AppError::syntax(name, 1, 1, e.to_string()))
+        }
+        Format::Toml => {
+            let table: toml::Table = contents.parse().map_err(|e: toml::de::Error| {
+                let offset = e.span().map_or(0, |span| span.start);
+                let (line, column) = line_and_column(contents, offset);
+                AppError::syntax(name, line, column, e.message().trim_end())
+            })?;
+            toml::to_string_pretty(&table).map_err(|e| AppError::syntax(name, 1, 1, e.to_string()))
+        }
+        Format::Text => Ok(contents.to_string()),
+    }
+}
+
+// 1-based line and column (in characters) of a byte offset
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(detect_format("data.csv", ""), Format::Csv);
+        assert_eq!(detect_format("Cargo.TOML", ""), Format::Toml);
+        assert_eq!(detect_format("noext", "  {\"a\": [1, 2]}"), Format::Json);
+        assert_eq!(detect_format("noext", "[1, 2]"), Format::Json);
+        assert_eq!(detect_format("noext", "# settings\n[server]\nport = 80\n[[users]]\nname = \"a\"\n"), Format::Toml);
+        assert_eq!(detect_format("noext", "a,b\n1,2\n3,4\n"), Format::Csv);
+        assert_eq!(detect_format("noext", "Hello, world\nno commas here\n"), Format::Text);
+    }
+
+    #[test]
+    fn pretty_prints_and_reports_positions() {
+        let pretty = render_str("a.json", "{\"b\":1,\"a\":[true,null]}", Format::Json).unwrap();
+        assert_eq!(pretty, "{\n  \"b\": 1,\n  \"a\": [\n    true,\n    null\n  ]\n}");
+
+        let error = render_str("a.json", "{\n  \"a\": 1,\n  \"b\" 2\n}", Format::Json).err().unwrap();
+        assert_eq!(error.to_string(), "a.json:3:7: expected `:`");
+
+        let toml = render_str("c.toml", "title=\"x\"\n[server]\nport=80", Format::Toml).unwrap();
+        assert_eq!(toml, "title = \"x\"\n\n[server]\nport = 80\n");
+        let error = render_str("c.toml", "a = 1\nb = \n", Format::Toml).err().unwrap();
+        assert!(error.to_string().starts_with("c.toml:2:5: "), "{}", error);
+    }
+}
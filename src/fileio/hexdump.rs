@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use crate::apperror::AppError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HexDumpOptions {
+    // Bytes per row
+    pub width: usize,
+    // Where to start, in bytes from the start of the file
+    pub offset: u64,
+    // How many bytes to show, None for up to the end of the file
+    pub length: Option<u64>,
+}
+
+impl Default for HexDumpOptions {
+    fn default() -> Self {
+        HexDumpOptions {
+            width: 16,
+            offset: 0,
+            length: None,
+        }
+    }
+}
+
+// Decimal or 0x-prefixed hex, for offsets and lengths typed by the user
+pub fn parse_number(text: &str) -> Option<u64> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// One row: offset, hex bytes split in groups of 8, then the printable ASCII gutter
+//     00000010  48 65 6c 6c 6f 20 77 6f  72 6c 64 0a              |Hello world.|
+pub fn format_row(offset: u64, bytes: &[u8], width: usize) -> String {
+    let mut row = format!("{:08x} ", offset);
+    for i in 0..width {
+        if i % 8 == 0 {
+            row.push(' ');
+        }
+        match bytes.get(i) {
+            Some(byte) => row.push_str(&format!("{:02x} ", byte)),
+            None => row.push_str("   "),
+        }
+    }
+    row.push_str(" |");
+    for byte in bytes {
+        row.push(if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' });
+    }
+    row.push('|');
+    row
+}
+
+// Streams the selected window of `path` one row at a time, `on_row` returns false to stop
+pub fn hexdump<F>(path: &str, options: HexDumpOptions, mut on_row: F) -> Result<(), AppError>
+where
+    F: FnMut(&str) -> bool,
+{
+    let width = options.width.max(1);
+    let mut file = File::open(super::resolve(path)?).map_err(|e| AppError::io("open", path, e))?;
+    file.seek(SeekFrom::Start(options.offset))
+        .map_err(|e| AppError::io("seek in", path, e))?;
+
+    let mut remaining = options.length.unwrap_or(u64::MAX);
+    let mut offset = options.offset;
+    let mut row = vec![0; width];
+
+    while remaining > 0 {
+        let wanted = (width as u64).min(remaining) as usize;
+        let mut filled = 0;
+        while filled < wanted {
+            let read = file
+                .read(&mut row[filled..wanted])
+                .map_err(|e| AppError::io("read", path, e))?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        if filled == 0 {
+            break;
+        }
+
+        if !on_row(&format_row(offset, &row[..filled], width)) {
+            break;
+        }
+        offset += filled as u64;
+        remaining -= filled as u64;
+        if filled < wanted {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::fileio::test_dir;
+
+    #[test]
+    fn formats_rows_with_gutter() {
+        assert_eq!(
+            format_row(16, b"Hello world\n", 16),
+            "00000010  48 65 6c 6c 6f 20 77 6f  72 6c 64 0a              |Hello world.|"
+        );
+        assert_eq!(format_row(0, &[0, 255], 4), "00000000  00 ff        |..|");
+    }
+
+    #[test]
+    fn dumps_a_window() {
+        let dir = test_dir("hexdump");
+        let path = dir.join("data.bin");
+        let path = path.to_str().unwrap();
+        fs::write(path, (0u8..40).collect::<Vec<_>>()).unwrap();
+
+        let mut rows = Vec::new();
+        let options = HexDumpOptions { width: 8, offset: 4, length: Some(10) };
+        hexdump(path, options, |row| {
+            rows.push(row.to_string());
+            true
+        })
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("00000004  04 05 06 07 08 09 0a 0b"));
+        assert!(rows[1].starts_with("0000000c  0c 0d "));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_number("256"), Some(256));
+        assert_eq!(parse_number("0x100"), Some(256));
+        assert_eq!(parse_number("zz"), None);
+    }
+}
@@ -0,0 +1,984 @@
+mod picker;
+
+use std::env;
+use std::io::{self, BufRead, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use log::error;
+use crate::apperror::AppError;
+use crate::menu::{get_file_menu_options, get_manage_menu_options};
+use crate::{flush_stdout, read_input};
+use picker::{prompt_path, Pick};
+use super::{
+    Algorithm, DiffOptions, Editor, Encoding, Format, HexDumpOptions, InvalidUtf8, LineEnding, LineSelection, Query,
+    EntryKind, Pipeline, SearchOptions, TarEntry, Trash, WatchMode, WatchOptions, WriteMode, SENTINEL,
+};
+
+// Used when the terminal height is unknown
+const DEFAULT_PAGE_SIZE: usize = 23;
+// Most frequent words listed by the text statistics
+const TOP_WORDS: usize = 10;
+// Lines of a message shown before asking to confirm the write
+const PREVIEW_LINES: usize = 10;
+// Passphrase attempts when opening an encrypted file
+const PASSPHRASE_TRIES: usize = 3;
+
+// Menu handlers: prompt for input, call the core functions in fileio.rs, print the outcome
+
+fn prompt(label: &str) -> String {
+    print!("{}", label);
+    flush_stdout();
+    read_input()
+}
+
+fn confirm(question: &str) -> bool {
+    let answer = prompt(&format!("{} (y/n): ", question));
+    answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
+}
+
+// Not echoed when there is a terminal. Piped input has none, then it is a plain prompt.
+fn prompt_passphrase(label: &str) -> String {
+    rpassword::prompt_password(label).unwrap_or_else(|_| prompt(label))
+}
+
+// Asked twice, a typo here would lock the file for good
+fn new_passphrase() -> Option<String> {
+    let passphrase = prompt_passphrase("Passphrase: ");
+    if passphrase.is_empty() {
+        println!("The passphrase can not be empty, nothing written.");
+        return None;
+    }
+    if prompt_passphrase("Repeat the passphrase: ") != passphrase {
+        println!("The passphrases do not match, nothing written.");
+        return None;
+    }
+    Some(passphrase)
+}
+
+// Prints lines a screenful at a time, waiting for Enter in between. 'q' stops early.
+struct Pager {
+    page_size: usize,
+    shown: usize,
+}
+
+impl Pager {
+    fn new() -> Self {
+        // Shells export LINES for the terminal height, keep one line free for the prompt
+        let page_size = env::var("LINES")
+            .ok()
+            .and_then(|lines| lines.parse::<usize>().ok())
+            .filter(|lines| *lines > 1)
+            .map(|lines| lines - 1)
+            .unwrap_or(DEFAULT_PAGE_SIZE);
+        Pager { page_size, shown: 0 }
+    }
+
+    // Returns false once the user has asked to stop
+    fn show(&mut self, line: &str) -> bool {
+        println!("{}", line);
+        self.shown += 1;
+        if self.shown.is_multiple_of(self.page_size) {
+            let answer = prompt("-- more -- (Enter to continue, q to quit) ");
+            return answer != "q";
+        }
+        true
+    }
+}
+
+// Asks for the message, shows a preview and confirms it. None when there is nothing to write.
+// A line typed at the first prompt is the whole message like before, an empty answer switches
+// to multi-line input (which is also how text piped into stdin is read).
+fn read_message() -> Option<String> {
+    let interactive = io::stdin().is_terminal();
+    read_message_from(&mut io::stdin().lock(), interactive)
+}
+
+// Like prompt, but from `input`
+fn answer_from<R: BufRead>(input: &mut R, label: &str) -> String {
+    print!("{}", label);
+    flush_stdout();
+    let mut answer = String::new();
+    if let Err(e) = input.read_line(&mut answer) {
+        error!("{:?}", AppError::io("read from", "stdin", e));
+    }
+    answer.trim().to_string()
+}
+
+// Piped input has nobody to answer the confirmation, and has usually run out by the time the
+// message is read, so without a terminal the preview is shown and the message written as is
+fn read_message_from<R: BufRead>(input: &mut R, interactive: bool) -> Option<String> {
+    let answer = answer_from(input, "Enter message (Enter alone for multi-line input, 'file PATH' to copy a file): ");
+
+    let message = if let Some(path) = answer.strip_prefix("file ") {
+        // Any encoding the file is in, like --from on the command line
+        match super::read_decoded(path.trim()) {
+            Ok((message, _)) => message,
+            Err(e) => {
+                error!("{:?}", e);
+                return None;
+            }
+        }
+    } else if answer.is_empty() {
+        println!("Type the message, end it with a line containing only '{}' or with Ctrl-D:", SENTINEL);
+        match super::read_until_sentinel(input, SENTINEL) {
+            Ok(message) => message,
+            Err(e) => {
+                error!("{:?}", AppError::io("read from", "stdin", e));
+                return None;
+            }
+        }
+    } else {
+        answer + "\n"
+    };
+
+    if message.is_empty() {
+        println!("The message is empty, nothing written.");
+        return None;
+    }
+    println!("{}", super::preview(&message, PREVIEW_LINES));
+    if interactive {
+        let answer = answer_from(input, "Write this? (y/n): ");
+        if !(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")) {
+            println!("Nothing written.");
+            return None;
+        }
+    }
+    Some(message)
+}
+
+pub fn read_file() {
+    while let Some(file_path) = prompt_path("Enter file path", Pick::ExistingFile) {
+        // Nothing about an encrypted file's contents can be told before it is decrypted
+        let encrypted = match super::is_encrypted_file(&file_path) {
+            Ok(encrypted) => encrypted,
+            Err(e) => {
+                error!("{:?}", e);
+                continue;
+            }
+        };
+        if encrypted {
+            match show_encrypted(&file_path) {
+                Ok(()) => break,
+                Err(e) => {
+                    error!("{:?}", e);
+                    continue;
+                }
+            }
+        }
+
+        // Ask how to show it only once the file is known to be readable
+        let encoding = match super::detect_file_encoding(&file_path) {
+            Ok(encoding) => encoding,
+            Err(e) => {
+                error!("{:?}", e);
+                continue;
+            }
+        };
+
+        let result = match encoding {
+            None => {
+                println!("{} is not text, showing a hex dump.", file_path);
+                show_hexdump(&file_path)
+            }
+            Some(Encoding::Utf8) => show_text(&file_path),
+            Some(_) => show_decoded(&file_path),
+        };
+        match result {
+            Ok(()) => break,
+            Err(e) => error!("{:?}", e),
+        }
+    }
+}
+
+// UTF-8 files are streamed, so even huge ones can be paged through
+fn show_text(path: &str) -> Result<(), AppError> {
+    let format = super::detect_file_format(path).unwrap_or(Format::Text);
+    if format != Format::Text && confirm(&format!("This looks like {}. Show it formatted?", format.name())) {
+        let mut pager = Pager::new();
+        return super::render(path, format, |line| pager.show(line));
+    }
+
+    let selection = loop {
+        let answer = prompt("Lines to show (Enter for all, 'head N', 'tail N', 'A-B' or 'hex'): ");
+        if answer.trim() == "hex" {
+            break None;
+        }
+        match LineSelection::parse(&answer) {
+            Some(selection) => break Some(selection),
+            None => println!("Not a valid selection."),
+        }
+    };
+    match selection {
+        Some(selection) => {
+            println!("File contents:");
+            let mut pager = Pager::new();
+            super::for_each_line(path, selection, InvalidUtf8::Lossy, |_, line| pager.show(line))
+        }
+        None => show_hexdump(path),
+    }
+}
+
+// Other encodings have to be decoded as a whole first
+fn show_decoded(path: &str) -> Result<(), AppError> {
+    let (text, encoding) = super::read_decoded(path)?;
+    let encoding = encoding.unwrap_or(Encoding::Latin1);
+    match LineEnding::detect(&text) {
+        Some(ending) => println!("File contents ({}, {} line endings):", encoding.name(), ending.name()),
+        None => println!("File contents ({}):", encoding.name()),
+    }
+    let mut pager = Pager::new();
+    for line in text.lines() {
+        if !pager.show(line) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Decrypted in memory only, the plain text is never written anywhere
+fn show_encrypted(path: &str) -> Result<(), AppError> {
+    println!("{} is encrypted.", path);
+    for _ in 0..PASSPHRASE_TRIES {
+        let plaintext = match super::read_encrypted(path, &prompt_passphrase("Passphrase: ")) {
+            Ok(plaintext) => plaintext,
+            Err(e @ AppError::Crypto { .. }) => {
+                println!("{}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let text = match super::detect_encoding(&plaintext) {
+            Some(encoding) => super::decode(&plaintext, encoding),
+            None => String::from_utf8_lossy(&plaintext).into_owned(),
+        };
+        println!("File contents:");
+        let mut pager = Pager::new();
+        for line in text.lines() {
+            if !pager.show(line) {
+                break;
+            }
+        }
+        return Ok(());
+    }
+    println!("Giving up after {} tries.", PASSPHRASE_TRIES);
+    Ok(())
+}
+
+fn show_hexdump(path: &str) -> Result<(), AppError> {
+    let mut options = HexDumpOptions::default();
+
+    let width = prompt(&format!("Bytes per row (Enter for {}): ", options.width));
+    if let Ok(width) = width.trim().parse::<usize>() {
+        options.width = width.clamp(1, 64);
+    }
+
+    let window = prompt("Window to show (Enter for the whole file, or 'OFFSET [LENGTH]', 0x for hex): ");
+    let mut numbers = window.split_whitespace().map(super::parse_number);
+    if let Some(Some(offset)) = numbers.next() {
+        options.offset = offset;
+    }
+    if let Some(Some(length)) = numbers.next() {
+        options.length = Some(length);
+    }
+
+    let mut pager = Pager::new();
+    super::hexdump(path, options, |row| pager.show(row))
+}
+
+pub fn write_into_file() {
+    let path = match prompt_path("Enter file path to write to", Pick::NewFile) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let (mode, backup) = if super::exists(&path) {
+        if !confirm(&format!("{} already exists, replace its contents?", path)) {
+            println!("Nothing written.");
+            return;
+        }
+        (WriteMode::Truncate, confirm(&format!("Keep the old contents in {}.bak?", path)))
+    } else {
+        (WriteMode::Create, false)
+    };
+
+    // A file that is replaced keeps its encoding and line endings unless asked otherwise. Those of
+    // an encrypted one are unknown without the passphrase.
+    let encrypted = mode == WriteMode::Truncate && super::is_encrypted_file(&path).unwrap_or(false);
+    let current = if mode == WriteMode::Truncate && !encrypted { super::read_decoded(&path).ok() } else { None };
+    let message = match read_message() {
+        Some(message) => message,
+        None => return,
+    };
+    let default_encoding = current.as_ref().and_then(|(_, encoding)| *encoding).unwrap_or(Encoding::Utf8);
+    let default_ending = current.as_ref().and_then(|(text, _)| LineEnding::detect(text));
+    let bytes = match choose_encoding(&message, default_encoding, default_ending) {
+        Some(bytes) => bytes,
+        None => return,
+    };
+    let question = if encrypted { "It is encrypted now, encrypt it again?" } else { "Encrypt it with a passphrase?" };
+    let passphrase = if confirm(question) {
+        match new_passphrase() {
+            Some(passphrase) => Some(passphrase),
+            None => return,
+        }
+    } else {
+        None
+    };
+
+    // Always atomic from the menu, a failed write must not leave a half written file behind
+    let result = match passphrase {
+        Some(passphrase) => {
+            if backup && !encrypted {
+                println!("Note: {}.bak keeps the old contents unencrypted.", path);
+            }
+            super::write_encrypted(&path, &bytes, &passphrase, mode, backup)
+        }
+        None => super::write_atomic(&path, &bytes, mode, backup),
+    };
+    match result {
+        Ok(()) => println!("Message written to file successfully!"),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+// Asks for the encoding and line endings to write `message` in, 'q' cancels
+fn choose_encoding(message: &str, encoding: Encoding, ending: Option<LineEnding>) -> Option<Vec<u8>> {
+    let default = match ending {
+        Some(ending) => format!("{}, {}", encoding.name(), ending.name()),
+        None => String::from(encoding.name()),
+    };
+    loop {
+        let answer = prompt(&format!(
+            "Encoding (utf-8, utf-8-bom, utf-16le, utf-16be, latin-1) and/or line endings (lf, crlf), Enter for {}: ",
+            default
+        ));
+        if answer == "q" {
+            return None;
+        }
+        let (mut encoding, mut ending) = (encoding, ending);
+        let mut valid = true;
+        for word in answer.split_whitespace() {
+            if let Some(chosen) = Encoding::parse(word) {
+                encoding = chosen;
+            } else if let Some(chosen) = LineEnding::parse(word) {
+                ending = Some(chosen);
+            } else {
+                println!("Unknown encoding or line ending '{}'", word);
+                valid = false;
+            }
+        }
+        if !valid {
+            continue;
+        }
+        let text = match ending {
+            Some(ending) => super::convert_line_endings(message, ending),
+            None => message.to_string(),
+        };
+        match super::encode(&text, encoding) {
+            Ok(bytes) => return Some(bytes),
+            Err(e) => println!("{}, pick another encoding or 'q' to cancel.", e),
+        }
+    }
+}
+
+pub fn append_to_file() {
+    let path = match prompt_path("Enter file path to append to", Pick::NewFile) {
+        Some(path) => path,
+        None => return,
+    };
+    // append() refuses them too, this just saves typing the message first
+    if super::exists(&path) && super::is_encrypted_file(&path).unwrap_or(false) {
+        println!("{} is encrypted, it can only be written as a whole.", path);
+        return;
+    }
+
+    let message = match read_message() {
+        Some(message) => message,
+        None => return,
+    };
+    match super::append_text(&path, &message, None) {
+        Ok(()) => println!("Message appended to file successfully!"),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+// Submenu for everything beyond read, write and append
+pub fn file_tools() {
+    loop {
+        let options = get_file_menu_options();
+        println!();
+        for option in &options {
+            println!("{} - {}", option.number, option.description);
+        }
+        let selection = prompt("Select file tool: ");
+        match selection.parse::<u32>() {
+            Ok(1) => edit_file(),
+            Ok(2) => search_files(),
+            Ok(3) => compare_files(),
+            Ok(4) => checksums(),
+            Ok(5) => query_csv(),
+            Ok(6) => watch_file(),
+            Ok(7) => manage_files(),
+            Ok(8) => tar_archives(),
+            Ok(9) => text_statistics(),
+            Ok(10) => transform_text(),
+            Ok(11) => break,
+            _ => println!("Invalid selection. Please enter a valid selection."),
+        }
+    }
+}
+
+fn edit_file() {
+    let path = match prompt_path("Enter file path to edit", Pick::NewFile) {
+        Some(path) => path,
+        None => return,
+    };
+    let mut editor = match Editor::open(&path) {
+        Ok(editor) => editor,
+        Err(e) => {
+            error!("{:?}", e);
+            return;
+        }
+    };
+    if editor.lines().is_empty() {
+        println!("{}: empty or new file", path);
+    } else {
+        println!("{}: {} lines", path, editor.lines().len());
+    }
+    println!("'h' for help, 'q' to quit");
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut stdout = io::stdout();
+    let mut command = String::new();
+    loop {
+        print!(":");
+        flush_stdout();
+        command.clear();
+        match input.read_line(&mut command) {
+            // End of input, there is nobody left to confirm anything
+            Ok(0) => {
+                if editor.is_dirty() {
+                    println!("\nInput ended, unsaved changes to {} discarded.", path);
+                }
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("{:?}", AppError::io("read from", "stdin", e));
+                break;
+            }
+        }
+        match editor.execute(&command, &mut input, &mut stdout) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(message) => println!("? {}", message),
+        }
+    }
+}
+
+fn search_files() {
+    let pattern = prompt("Search for: ");
+    if pattern.is_empty() {
+        return;
+    }
+    let path = match prompt_path("File or directory to search", Pick::FileOrDir) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let mut options = SearchOptions::default();
+    let answer = prompt("Options, any of 'regex', 'i' (ignore case), 'w' (whole word), 'C N' (context), Enter for none: ");
+    let mut words = answer.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "regex" => options.regex = true,
+            "i" => options.ignore_case = true,
+            "w" => options.whole_word = true,
+            "C" => options.context = words.next().and_then(|n| n.parse().ok()).unwrap_or(2),
+            other => println!("Ignoring unknown option '{}'", other),
+        }
+    }
+
+    let matcher = match super::build_matcher(&pattern, options) {
+        Ok(matcher) => matcher,
+        Err(e) => {
+            println!("Invalid pattern: {}", e);
+            return;
+        }
+    };
+    let mut pager = Pager::new();
+    match super::search(&path, &matcher, options.context, |line| pager.show(line)) {
+        Ok(0) => println!("No matches."),
+        Ok(1) => println!("1 matching line."),
+        Ok(count) => println!("{} matching lines.", count),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn compare_files() {
+    let old_path = match prompt_path("Old file", Pick::ExistingFile) {
+        Some(path) => path,
+        None => return,
+    };
+    let new_path = match prompt_path("New file", Pick::ExistingFile) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let mut options = DiffOptions::default();
+    let answer = prompt("Options, 'side' (side by side) or 'U N' (context lines), Enter for unified with 3: ");
+    let mut words = answer.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "side" => options.side_by_side = true,
+            "U" => options.context = words.next().and_then(|n| n.parse().ok()).unwrap_or(3),
+            other => println!("Ignoring unknown option '{}'", other),
+        }
+    }
+
+    let mut pager = Pager::new();
+    match super::diff(&old_path, &new_path, options, |line| pager.show(line)) {
+        Ok(false) => println!("The files are identical."),
+        Ok(true) => {}
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn checksums() {
+    let answer = prompt("Algorithm, one of crc32, md5, sha1, sha256 (Enter for sha256), or 'verify' to check a manifest: ");
+    if answer == "verify" {
+        let manifest = match prompt_path("Checksum manifest", Pick::ExistingFile) {
+            Some(path) => path,
+            None => return,
+        };
+        let mut pager = Pager::new();
+        match super::verify(&manifest, None, |line| pager.show(line)) {
+            Ok(summary) if summary.failed == 0 && summary.unreadable == 0 => {
+                println!("All {} files OK.", summary.ok)
+            }
+            Ok(summary) => println!(
+                "{} OK, {} FAILED, {} could not be checked.",
+                summary.ok, summary.failed, summary.unreadable
+            ),
+            Err(e) => error!("{:?}", e),
+        }
+        return;
+    }
+
+    let algorithm = if answer.is_empty() { Some(Algorithm::Sha256) } else { Algorithm::parse(&answer) };
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None => {
+            println!("Unknown algorithm '{}'", answer);
+            return;
+        }
+    };
+    let path = match prompt_path("File to hash", Pick::ExistingFile) {
+        Some(path) => path,
+        None => return,
+    };
+    // Same layout as sha256sum, so the line can be pasted into a manifest
+    match super::checksum(&path, algorithm) {
+        Ok(digest) => println!("{}  {}", digest, path),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn query_csv() {
+    let path = match prompt_path("CSV file", Pick::ExistingFile) {
+        Some(path) => path,
+        None => return,
+    };
+    let records = match super::read_csv(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            error!("{:?}", e);
+            return;
+        }
+    };
+    if let Some(header) = records.first() {
+        println!("Columns: {}", header.join(", "));
+    }
+
+    loop {
+        let text = prompt("Query, e.g. 'select city, count, avg(age) where age > 20 group by city order by count desc' (Enter to finish): ");
+        if text.is_empty() {
+            break;
+        }
+        let result = match Query::parse(&text).and_then(|query| query.run(&records)) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("? {}", e);
+                continue;
+            }
+        };
+        let mut pager = Pager::new();
+        for line in super::format_table(&result) {
+            if !pager.show(&line) {
+                break;
+            }
+        }
+        println!("{} rows.", result.len().saturating_sub(1));
+
+        let out = prompt("Save the result as CSV (path, Enter to skip): ");
+        if out.is_empty() {
+            continue;
+        }
+        let mode = if super::exists(&out) {
+            if !confirm(&format!("{} already exists. Replace it?", out)) {
+                continue;
+            }
+            WriteMode::Truncate
+        } else {
+            WriteMode::Create
+        };
+        let csv = super::format_csv(&result, super::delimiter_for(&out));
+        match super::write(&out, csv.as_bytes(), mode) {
+            Ok(()) => println!("Saved to {}", out),
+            Err(e) => error!("{:?}", e),
+        }
+    }
+}
+
+fn watch_file() {
+    let path = match prompt_path("File to watch", Pick::ExistingFile) {
+        Some(path) => path,
+        None => return,
+    };
+    let mut options = WatchOptions::default();
+    if confirm("Only show appended lines, like tail -f?") {
+        options.mode = WatchMode::Tail;
+    }
+
+    // The terminal is line buffered, so stopping waits for Enter on a thread of its own
+    println!("Watching {}, press Enter to stop.", path);
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopper = {
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let mut line = String::new();
+            let _ = io::stdin().read_line(&mut line);
+            stop.store(true, Ordering::Relaxed);
+        })
+    };
+
+    let result = super::watch(&path, options, |line| {
+        println!("{}", line);
+        true
+    }, || stop.load(Ordering::Relaxed));
+    if let Err(e) = result {
+        error!("{:?}", e);
+        println!("Press Enter to return to the menu.");
+    }
+    // Either way the thread has taken or is waiting for the Enter, so it must be joined before
+    // the menu reads stdin again
+    let _ = stopper.join();
+    println!("Stopped watching {}.", path);
+}
+
+fn manage_files() {
+    loop {
+        let options = get_manage_menu_options();
+        println!();
+        for option in &options {
+            println!("{} - {}", option.number, option.description);
+        }
+        let selection = prompt("Select operation: ");
+        match selection.parse::<u32>() {
+            Ok(1) => copy_or_move(false),
+            Ok(2) => copy_or_move(true),
+            Ok(3) => delete_to_trash(),
+            Ok(4) => restore_from_trash(),
+            Ok(5) => empty_trash(),
+            Ok(6) => new_directory(),
+            Ok(7) => break,
+            _ => println!("Invalid selection. Please enter a valid selection."),
+        }
+    }
+}
+
+fn copy_or_move(moving: bool) {
+    let verb = if moving { "move" } else { "copy" };
+    let from = match prompt_path(&format!("Path to {}", verb), Pick::FileOrDir) {
+        Some(path) => path,
+        None => return,
+    };
+    let to = match prompt_path("Destination (an existing directory puts it inside)", Pick::NewFile) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let run = |overwrite| if moving { super::move_path(&from, &to, overwrite).map(|_| 0) } else { super::copy(&from, &to, overwrite) };
+    // Ask before replacing anything, the functions themselves refuse without overwrite
+    let result = match run(false) {
+        Err(e) if matches!(&e, AppError::Io { source, .. } if source.kind() == io::ErrorKind::AlreadyExists) => {
+            println!("{}", e);
+            if !confirm("Replace it?") {
+                return;
+            }
+            run(true)
+        }
+        other => other,
+    };
+    match result {
+        Ok(_) if moving => println!("Moved {} to {}.", from, to),
+        Ok(bytes) => println!("Copied {} to {} ({}).", from, to, super::format_size(bytes)),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn delete_to_trash() {
+    let path = match prompt_path("Path to delete", Pick::FileOrDir) {
+        Some(path) => path,
+        None => return,
+    };
+    if !confirm(&format!("Move {} to the trash?", path)) {
+        return;
+    }
+    match Trash::local().put(&path) {
+        Ok(entry) => println!("Moved {} to the trash as {}.", path, entry.id),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn restore_from_trash() {
+    let trash = Trash::local();
+    let entries = match trash.list() {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("{:?}", e);
+            return;
+        }
+    };
+    if entries.is_empty() {
+        println!("The trash is empty.");
+        return;
+    }
+    for (number, entry) in entries.iter().enumerate() {
+        let kind = if entry.is_dir { "/" } else { "" };
+        println!("  {:>3}) {}{}  deleted {} UTC", number + 1, entry.original, kind, super::format_time(entry.deleted));
+    }
+    let answer = prompt("Number to restore, Enter to cancel: ");
+    let entry = match answer.parse::<usize>().ok().and_then(|number| entries.get(number.wrapping_sub(1))) {
+        Some(entry) => entry,
+        None => return,
+    };
+
+    let overwrite = super::exists(&entry.original);
+    if overwrite && !confirm(&format!("{} exists again. Replace it?", entry.original)) {
+        return;
+    }
+    match trash.restore(&entry.id, overwrite) {
+        Ok(path) => println!("Restored {}.", path),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn empty_trash() {
+    let trash = Trash::local();
+    let count = match trash.list() {
+        Ok(entries) => entries.len(),
+        Err(e) => {
+            error!("{:?}", e);
+            return;
+        }
+    };
+    if count == 0 {
+        println!("The trash is empty.");
+        return;
+    }
+    if !confirm(&format!("Permanently delete the {} entries in the trash?", count)) {
+        return;
+    }
+    match trash.empty() {
+        Ok(removed) => println!("Deleted {} entries for good.", removed),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn new_directory() {
+    let path = prompt("New directory path (parents are created too): ");
+    if path.is_empty() {
+        return;
+    }
+    match super::make_dir(&path) {
+        Ok(()) => println!("Created {}.", path),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+// One line per entry, like tar -tv without owners
+fn describe_entry(entry: &TarEntry) -> String {
+    let size = match entry.kind {
+        EntryKind::File => super::format_size(entry.size),
+        _ => String::from("-"),
+    };
+    let link = if entry.link.is_empty() { String::new() } else { format!(" -> {}", entry.link) };
+    format!("{:>8}  {}  {}{}", size, super::format_time(entry.modified), entry.path, link)
+}
+
+fn tar_archives() {
+    let answer = prompt("'list', 'create' or 'extract' a tar archive: ");
+    match answer.as_str() {
+        "list" => list_archive(),
+        "create" => create_archive(),
+        "extract" => extract_archive(),
+        _ => println!("Unknown action '{}'", answer),
+    }
+}
+
+fn list_archive() {
+    let archive = match prompt_path("Tar archive", Pick::ExistingFile) {
+        Some(path) => path,
+        None => return,
+    };
+    let mut pager = Pager::new();
+    match super::list_tar(&archive, |entry| pager.show(&describe_entry(entry))) {
+        Ok(count) => println!("{} entries.", count),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn create_archive() {
+    let archive = match prompt_path("Archive to create", Pick::NewFile) {
+        Some(path) => path,
+        None => return,
+    };
+    let mode = if super::exists(&archive) {
+        if !confirm(&format!("{} exists. Replace it?", archive)) {
+            return;
+        }
+        WriteMode::Overwrite
+    } else {
+        WriteMode::Create
+    };
+
+    println!("Add files and directories one at a time, 'q' when done.");
+    let mut paths = Vec::new();
+    while let Some(path) = prompt_path("Path to add", Pick::FileOrDir) {
+        paths.push(path);
+    }
+    if paths.is_empty() {
+        println!("Nothing to archive.");
+        return;
+    }
+    let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+    match super::create_tar(&archive, &paths, mode, |entry| println!("a {}", entry.path)) {
+        Ok(count) => println!("Wrote {} entries to {}.", count, archive),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn extract_archive() {
+    let archive = match prompt_path("Tar archive", Pick::ExistingFile) {
+        Some(path) => path,
+        None => return,
+    };
+    let dest = prompt("Directory to extract into (Enter for the current one): ");
+    let dest = if dest.is_empty() { String::from(".") } else { dest };
+
+    let run = |overwrite| {
+        super::extract_tar(&archive, &dest, overwrite, |entry| match entry.kind {
+            EntryKind::File | EntryKind::Dir => println!("x {}", entry.path),
+            _ => println!("skipped {} (only files and directories are extracted)", describe_entry(entry)),
+        })
+    };
+    let result = match run(false) {
+        Err(e) if matches!(&e, AppError::Io { source, .. } if source.kind() == io::ErrorKind::AlreadyExists) => {
+            println!("{}", e);
+            if !confirm("Replace existing files?") {
+                return;
+            }
+            run(true)
+        }
+        other => other,
+    };
+    match result {
+        Ok(count) => println!("Extracted {} entries into {}.", count, dest),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+fn text_statistics() {
+    println!("Pick one file, or several to compare them, 'q' when done.");
+    let mut files = Vec::new();
+    while let Some(path) = prompt_path("File", Pick::ExistingFile) {
+        match super::text_stats(&path) {
+            Ok(stats) => files.push((path, stats)),
+            Err(e) => error!("{:?}", e),
+        }
+    }
+    if files.is_empty() {
+        return;
+    }
+    let mut pager = Pager::new();
+    for line in super::stats_report(&files, TOP_WORDS) {
+        if !pager.show(&line) {
+            break;
+        }
+    }
+}
+
+fn transform_text() {
+    let path = match prompt_path("File to transform", Pick::ExistingFile) {
+        Some(path) => path,
+        None => return,
+    };
+    println!("Steps: sort [-r] [-n], uniq, reverse, upper, lower, trim, replace /regex/replacement/[i], number");
+    let pipeline = loop {
+        let answer = prompt("Pipeline, e.g. 'sort | uniq | upper' (Enter to cancel): ");
+        if answer.is_empty() {
+            return;
+        }
+        match Pipeline::parse(&answer) {
+            Ok(pipeline) => break pipeline,
+            Err(e) => println!("{}", e),
+        }
+    };
+    let (result, encoding) = match super::transform_file(&path, &pipeline) {
+        Ok(transformed) => transformed,
+        Err(e) => {
+            error!("{:?}", e);
+            return;
+        }
+    };
+
+    println!("{}", super::preview(&result, PREVIEW_LINES));
+    let out = prompt("Write the result to (Enter for the same file, 'q' to discard): ");
+    if out == "q" {
+        return;
+    }
+    let out = if out.is_empty() { path } else { out };
+    if super::exists(&out) && !confirm(&format!("Replace the contents of {}?", out)) {
+        return;
+    }
+    // Atomic, so a pipeline run over the file it reads from can not leave it half written. The
+    // result keeps the encoding of the file it came from.
+    let written = super::encode(&result, encoding).and_then(|bytes| super::write_atomic(&out, &bytes, WriteMode::Overwrite, false));
+    match written {
+        Ok(()) => println!("Wrote {}.", out),
+        Err(e) => error!("{:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_piped_messages_without_asking() {
+        let mut piped = io::Cursor::new("\nfirst line\nsecond line\n");
+        assert_eq!(read_message_from(&mut piped, false).unwrap(), "first line\nsecond line\n");
+
+        // With a terminal the answer comes after the message
+        let mut typed = io::Cursor::new("\nhello\n.\nn\n");
+        assert_eq!(read_message_from(&mut typed, true), None);
+        let mut typed = io::Cursor::new("hello\ny\n");
+        assert_eq!(read_message_from(&mut typed, true).unwrap(), "hello\n");
+        // Input that runs out before the answer writes nothing
+        let mut typed = io::Cursor::new("\nhello\n");
+        assert_eq!(read_message_from(&mut typed, true), None);
+    }
+}
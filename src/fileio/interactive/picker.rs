@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+use log::error;
+use crate::fileio::{exists, format_size, format_time, list_dir, parent_dir, resolve, suggest_paths};
+use super::prompt;
+
+// What the user is choosing a path for
+#[derive(Clone, Copy, PartialEq)]
+pub enum Pick {
+    // A file that has to exist, e.g. for reading
+    ExistingFile,
+    // An existing file, or a new one named from inside the browser
+    NewFile,
+    // A file or a whole directory, e.g. for searching
+    FileOrDir,
+}
+
+// Path prompt used by the file menus. 'b' opens the directory browser, 'q' cancels, and a path
+// that does not exist offers completions instead of failing (unless a new file is wanted).
+pub fn prompt_path(label: &str, pick: Pick) -> Option<String> {
+    loop {
+        let answer = prompt(&format!("{} ('b' to browse, 'q' to cancel): ", label));
+        match answer.as_str() {
+            "q" => return None,
+            "b" => match browse(Path::new("."), pick) {
+                Some(path) => return Some(path),
+                None => continue,
+            },
+            "" => continue,
+            _ => {}
+        }
+        // Paths outside the allowed root are refused here already, not only when opened
+        if let Err(e) = resolve(&answer) {
+            println!("{}", e);
+            continue;
+        }
+        if pick == Pick::NewFile || exists(&answer) {
+            return Some(answer);
+        }
+
+        let suggestions = suggest_paths(&answer);
+        if suggestions.is_empty() {
+            println!("{} does not exist.", answer);
+            continue;
+        }
+        println!("{} does not exist. Did you mean:", answer);
+        for (number, suggestion) in suggestions.iter().enumerate() {
+            println!("  {}) {}", number + 1, suggestion);
+        }
+        let choice = prompt("Number to pick one, Enter to type the path again: ");
+        let picked = choice
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| suggestions.get(number.wrapping_sub(1)));
+        match picked {
+            // A directory only narrows things down, continue in the browser from there
+            Some(path) if path.ends_with('/') && pick != Pick::FileOrDir => {
+                if let Some(path) = browse(Path::new(path), pick) {
+                    return Some(path);
+                }
+            }
+            Some(path) => return Some(path.trim_end_matches('/').to_string()),
+            None => {}
+        }
+    }
+}
+
+// Lists a directory with sizes and modification times. Numbers open directories or pick files.
+fn browse(start: &Path, pick: Pick) -> Option<String> {
+    let mut dir = PathBuf::from(start);
+    loop {
+        let entries = match list_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("{:?}", e);
+                // Up one level, unless there is nowhere left to go (filesystem or allowed root)
+                let parent = parent_dir(&dir);
+                if parent == dir {
+                    return None;
+                }
+                dir = parent;
+                continue;
+            }
+        };
+
+        println!("\n{}", dir.display());
+        println!("  {:>3}) ../", 0);
+        for (number, entry) in entries.iter().enumerate() {
+            let modified = entry.modified.map(format_time).unwrap_or_else(|| String::from("-"));
+            if entry.is_dir {
+                println!("  {:>3}) {:<32} {:>8}  {}", number + 1, format!("{}/", entry.name), "-", modified);
+            } else {
+                println!("  {:>3}) {:<32} {:>8}  {}", number + 1, entry.name, format_size(entry.size), modified);
+            }
+        }
+
+        let mut help = String::from("Number to open, 'q' to cancel");
+        if pick == Pick::NewFile {
+            help.push_str(", 'n NAME' for a new file here");
+        }
+        if pick == Pick::FileOrDir {
+            help.push_str(", '.' to pick this directory");
+        }
+        let answer = prompt(&format!("{}: ", help));
+
+        match answer.as_str() {
+            "q" => return None,
+            "." if pick == Pick::FileOrDir => return Some(dir.to_string_lossy().into_owned()),
+            _ => {}
+        }
+        if let Some(name) = answer.strip_prefix("n ") {
+            if pick == Pick::NewFile && !name.trim().is_empty() {
+                return Some(dir.join(name.trim()).to_string_lossy().into_owned());
+            }
+        }
+        match answer.parse::<usize>() {
+            Ok(0) => dir = parent_dir(&dir),
+            Ok(number) if number <= entries.len() => {
+                let entry = &entries[number - 1];
+                let path = dir.join(&entry.name);
+                if entry.is_dir {
+                    dir = path;
+                } else {
+                    return Some(path.to_string_lossy().into_owned());
+                }
+            }
+            _ => println!("Invalid selection."),
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::apperror::AppError;
+use super::resolve;
+
+// Where deleted files go, relative to the working directory (or the root when there is one)
+pub const TRASH_DIR: &str = ".underpass-trash";
+
+fn error(kind: io::ErrorKind, message: &str) -> io::Error {
+    io::Error::new(kind, message.to_string())
+}
+
+// Copying or moving onto an existing directory puts the source inside it, like cp and mv. A
+// symlink to a directory is not followed, it counts as an existing file.
+fn destination(from: &Path, to: &Path) -> PathBuf {
+    match from.file_name() {
+        Some(name) if fs::symlink_metadata(to).is_ok_and(|metadata| metadata.is_dir()) => to.join(name),
+        _ => to.to_path_buf(),
+    }
+}
+
+// An existing file at the destination is only replaced with `overwrite`, a directory never is
+fn check_target(target: &Path, overwrite: bool, action: &'static str, shown: &str) -> Result<(), AppError> {
+    match fs::symlink_metadata(target) {
+        Ok(metadata) if metadata.is_dir() => {
+            Err(AppError::io(action, shown, error(io::ErrorKind::IsADirectory, "a directory with that name exists")))
+        }
+        Ok(_) if !overwrite => {
+            Err(AppError::io(action, shown, error(io::ErrorKind::AlreadyExists, "the destination already exists")))
+        }
+        _ => Ok(()),
+    }
+}
+
+// A hard link or a symlink to the source counts too. fs::copy truncates the target before
+// reading the source, so copying a file onto itself would empty it.
+#[cfg(unix)]
+fn same_file(source: &Path, target: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(source), fs::metadata(target)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(source: &Path, target: &Path) -> bool {
+    match (fs::canonicalize(source), fs::canonicalize(target)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// Copies a file, or a directory with everything in it. Returns the number of bytes copied.
+pub fn copy(from: &str, to: &str, overwrite: bool) -> Result<u64, AppError> {
+    let source = PathBuf::from(resolve(from)?);
+    let target = destination(&source, Path::new(&resolve(to)?));
+    let metadata = fs::metadata(&source).map_err(|e| AppError::io("copy", from, e))?;
+    if same_file(&source, &target) {
+        return Err(AppError::io("copy", from, error(io::ErrorKind::InvalidInput, "source and destination are the same file")));
+    }
+    check_target(&target, overwrite, "copy to", to)?;
+
+    if metadata.is_dir() {
+        let real_source = fs::canonicalize(&source).map_err(|e| AppError::io("copy", from, e))?;
+        if absolute(&target).starts_with(&real_source) {
+            return Err(AppError::io("copy", from, error(io::ErrorKind::InvalidInput, "can not copy a directory into itself")));
+        }
+        copy_dir(&source, &target).map_err(|e| AppError::io("copy", from, e))
+    } else {
+        // fs::copy would write through a symlink that is being replaced
+        if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            fs::remove_file(&target).map_err(|e| AppError::io("replace", to, e))?;
+        }
+        fs::copy(&source, &target).map_err(|e| AppError::io("copy", from, e))
+    }
+}
+
+// The link itself, pointing where the original did. Following it could copy things from
+// outside the root, and leaving it out would lose it when a move falls back to copying.
+#[cfg(unix)]
+fn copy_link(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, target)
+}
+
+#[cfg(not(unix))]
+fn copy_link(source: &Path, _target: &Path) -> io::Result<()> {
+    let message = format!("can not copy the symbolic link {}", source.display());
+    Err(io::Error::new(io::ErrorKind::Unsupported, message))
+}
+
+fn copy_dir(source: &Path, target: &Path) -> io::Result<u64> {
+    fs::create_dir(target)?;
+    let mut copied = 0;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let to = target.join(entry.file_name());
+        if file_type.is_dir() {
+            copied += copy_dir(&entry.path(), &to)?;
+        } else if file_type.is_file() {
+            copied += fs::copy(entry.path(), &to)?;
+        } else if file_type.is_symlink() {
+            copy_link(&entry.path(), &to)?;
+        }
+    }
+    Ok(copied)
+}
+
+// Moves or renames a file or directory. Across filesystems it is copied and the original removed.
+pub fn move_path(from: &str, to: &str, overwrite: bool) -> Result<(), AppError> {
+    let source = PathBuf::from(resolve(from)?);
+    let target = destination(&source, Path::new(&resolve(to)?));
+    fs::symlink_metadata(&source).map_err(|e| AppError::io("move", from, e))?;
+    check_target(&target, overwrite, "move to", to)?;
+    rename(&source, &target).map_err(|e| AppError::io("move", from, e))
+}
+
+fn rename(source: &Path, target: &Path) -> io::Result<()> {
+    match fs::rename(source, target) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => move_by_copy(source, target),
+        result => result,
+    }
+}
+
+// The original is only removed once everything, links included, has been copied
+fn move_by_copy(source: &Path, target: &Path) -> io::Result<()> {
+    let file_type = fs::symlink_metadata(source)?.file_type();
+    if file_type.is_dir() {
+        copy_dir(source, target)?;
+        fs::remove_dir_all(source)
+    } else if file_type.is_symlink() {
+        copy_link(source, target)?;
+        fs::remove_file(source)
+    } else {
+        fs::copy(source, target)?;
+        fs::remove_file(source)
+    }
+}
+
+// Creates a directory and any missing parents. Fails if something with that name exists already.
+pub fn make_dir(path: &str) -> Result<(), AppError> {
+    let resolved = resolve(path)?;
+    if fs::symlink_metadata(&resolved).is_ok() {
+        return Err(AppError::io("create directory", path, error(io::ErrorKind::AlreadyExists, "it already exists")));
+    }
+    fs::create_dir_all(&resolved).map_err(|e| AppError::io("create directory", path, e))
+}
+
+// The path made absolute without following a final symlink, so that is what gets trashed
+fn absolute(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let parent = fs::canonicalize(parent)
+        .or_else(|_| env::current_dir().map(|dir| dir.join(parent)))
+        .unwrap_or_else(|_| parent.to_path_buf());
+    match path.file_name() {
+        Some(name) => parent.join(name),
+        None => parent,
+    }
+}
+
+// Something in the trash and where it came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashEntry {
+    pub id: String,
+    pub original: String,
+    pub deleted: SystemTime,
+    pub is_dir: bool,
+}
+
+// A trash directory with the deleted files under files/ and, for each, a note of its original
+// path and deletion time under info/, the same split the freedesktop trash uses
+pub struct Trash {
+    dir: String,
+}
+
+impl Trash {
+    pub fn new(dir: &str) -> Trash {
+        Trash { dir: dir.to_string() }
+    }
+
+    // The trash in the working directory
+    pub fn local() -> Trash {
+        Trash::new(TRASH_DIR)
+    }
+
+    fn files(&self) -> Result<PathBuf, AppError> {
+        Ok(Path::new(&resolve(&self.dir)?).join("files"))
+    }
+
+    fn info(&self) -> Result<PathBuf, AppError> {
+        Ok(Path::new(&resolve(&self.dir)?).join("info"))
+    }
+
+    // Moves `path` into the trash, from where restore can bring it back
+    pub fn put(&self, path: &str) -> Result<TrashEntry, AppError> {
+        let source = absolute(Path::new(&resolve(path)?));
+        let metadata = fs::symlink_metadata(&source).map_err(|e| AppError::io("delete", path, e))?;
+        let (files, info) = (self.files()?, self.info()?);
+        if absolute(&files).starts_with(&source) {
+            return Err(AppError::io("delete", path, error(io::ErrorKind::InvalidInput, "it contains the trash")));
+        }
+        fs::create_dir_all(&files)
+            .and_then(|_| fs::create_dir_all(&info))
+            .map_err(|e| AppError::io("create", &self.dir, e))?;
+
+        let deleted = SystemTime::now();
+        let since_epoch = deleted.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seconds = since_epoch.as_secs();
+        let name = source.file_name().map_or(String::from("item"), |name| name.to_string_lossy().into_owned());
+        // The deletion time keeps ids apart, the counter handles the same name twice in one second
+        let mut id = format!("{}-{}", seconds, name);
+        let mut n = 1;
+        while fs::symlink_metadata(files.join(&id)).is_ok() || info.join(format!("{}.info", id)).exists() {
+            n += 1;
+            id = format!("{}-{}-{}", seconds, n, name);
+        }
+
+        let original = source.to_string_lossy().into_owned();
+        // Nanoseconds, so entries deleted within the same second still list in order
+        let note = format!("path={}\ndeleted={}\n", original, since_epoch.as_nanos());
+        let info_path = info.join(format!("{}.info", id));
+        fs::write(&info_path, note).map_err(|e| AppError::io("write to", info_path.to_string_lossy(), e))?;
+        if let Err(e) = rename(&source, &files.join(&id)) {
+            let _ = fs::remove_file(&info_path);
+            return Err(AppError::io("delete", path, e));
+        }
+        Ok(TrashEntry { id, original, deleted, is_dir: metadata.is_dir() })
+    }
+
+    // Oldest first. An empty list when nothing was ever deleted.
+    pub fn list(&self) -> Result<Vec<TrashEntry>, AppError> {
+        let (files, info) = (self.files()?, self.info()?);
+        let notes = match fs::read_dir(&info) {
+            Ok(notes) => notes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(AppError::io("read", info.to_string_lossy(), e)),
+        };
+
+        let mut entries = Vec::new();
+        for note in notes {
+            let note = note.map_err(|e| AppError::io("read", info.to_string_lossy(), e))?;
+            let file_name = note.file_name().to_string_lossy().into_owned();
+            let id = match file_name.strip_suffix(".info") {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+            let text = fs::read_to_string(note.path()).map_err(|e| AppError::io("read", note.path().to_string_lossy(), e))?;
+            let mut original = None;
+            let mut nanos = 0;
+            for line in text.lines() {
+                if let Some(path) = line.strip_prefix("path=") {
+                    original = Some(path.to_string());
+                } else if let Some(value) = line.strip_prefix("deleted=") {
+                    nanos = value.parse().unwrap_or(0);
+                }
+            }
+            // A note without its file is left over from a failed delete, skip it
+            let (original, metadata) = match (original, fs::symlink_metadata(files.join(&id))) {
+                (Some(original), Ok(metadata)) => (original, metadata),
+                _ => continue,
+            };
+            entries.push(TrashEntry {
+                id,
+                original,
+                deleted: UNIX_EPOCH + Duration::from_nanos(nanos),
+                is_dir: metadata.is_dir(),
+            });
+        }
+        entries.sort_by(|a, b| a.deleted.cmp(&b.deleted).then_with(|| a.id.cmp(&b.id)));
+        Ok(entries)
+    }
+
+    // Puts an entry back where it was deleted from, recreating missing parent directories.
+    // Returns the restored path.
+    pub fn restore(&self, id: &str, overwrite: bool) -> Result<String, AppError> {
+        let entry = self
+            .list()?
+            .into_iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| AppError::not_found("Trash entry", id))?;
+        let target = PathBuf::from(resolve(&entry.original)?);
+        check_target(&target, overwrite, "restore to", &entry.original)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| AppError::io("create", parent.to_string_lossy(), e))?;
+        }
+        rename(&self.files()?.join(id), &target).map_err(|e| AppError::io("restore", &entry.original, e))?;
+        // The file is back already, a stale note is skipped by list
+        let _ = fs::remove_file(self.info()?.join(format!("{}.info", id)));
+        Ok(entry.original)
+    }
+
+    // Deletes everything in the trash for good, returns how many entries there were
+    pub fn empty(&self) -> Result<usize, AppError> {
+        let entries = self.list()?;
+        let (files, info) = (self.files()?, self.info()?);
+        for entry in &entries {
+            let path = files.join(&entry.id);
+            let removed = if entry.is_dir { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+            removed.map_err(|e| AppError::io("delete", path.to_string_lossy(), e))?;
+            let _ = fs::remove_file(info.join(format!("{}.info", entry.id)));
+        }
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fileio::test_dir;
+
+    fn text(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn copies_and_moves_files_and_directories() {
+        let dir = test_dir("manage-copy");
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        fs::create_dir_all(dir.join("src/sub")).unwrap();
+        fs::write(dir.join("src/a.txt"), "a").unwrap();
+        fs::write(dir.join("src/sub/b.txt"), "bb").unwrap();
+
+        assert_eq!(copy(&path("src"), &path("copy"), false).unwrap(), 3);
+        assert_eq!(text(&dir.join("copy/sub/b.txt")), "bb");
+        // Onto an existing directory the source goes inside it
+        copy(&path("src/a.txt"), &path("copy/sub"), false).unwrap();
+        assert_eq!(text(&dir.join("copy/sub/a.txt")), "a");
+        assert!(copy(&path("src/a.txt"), &path("copy/sub/a.txt"), false).is_err());
+        assert!(copy(&path("src"), &path("src/sub/inner"), false).is_err());
+
+        move_path(&path("copy/sub/a.txt"), &path("renamed.txt"), false).unwrap();
+        assert!(!dir.join("copy/sub/a.txt").exists());
+        assert_eq!(text(&dir.join("renamed.txt")), "a");
+        fs::write(dir.join("other.txt"), "other").unwrap();
+        assert!(move_path(&path("other.txt"), &path("renamed.txt"), false).is_err());
+        move_path(&path("other.txt"), &path("renamed.txt"), true).unwrap();
+        assert_eq!(text(&dir.join("renamed.txt")), "other");
+
+        make_dir(&path("new/nested")).unwrap();
+        assert!(dir.join("new/nested").is_dir());
+        assert!(make_dir(&path("new")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_copy_a_file_onto_itself() {
+        let dir = test_dir("manage-same");
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        fs::write(dir.join("a.txt"), "keep me").unwrap();
+        fs::hard_link(dir.join("a.txt"), dir.join("link.txt")).unwrap();
+
+        for to in ["a.txt", "link.txt", "."] {
+            let e = copy(&path("a.txt"), &path(to), true).unwrap_err();
+            assert_eq!(format!("{:?}", e), format!("Failed to copy {}\n  caused by: source and destination are the same file", path("a.txt")));
+        }
+        assert_eq!(text(&dir.join("a.txt")), "keep me");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn deletes_to_the_trash_and_restores() {
+        let dir = test_dir("manage-trash");
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let trash = Trash::new(&path("trash"));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("docs/note.txt"), "first").unwrap();
+
+        let entry = trash.put(&path("docs/note.txt")).unwrap();
+        assert!(!dir.join("docs/note.txt").exists());
+        fs::write(dir.join("docs/note.txt"), "second").unwrap();
+        trash.put(&path("docs/note.txt")).unwrap();
+        trash.put(&path("docs")).unwrap();
+        assert!(trash.put(&path("trash")).is_err());
+
+        let entries = trash.list().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], entry);
+        assert!(entries[2].is_dir);
+
+        // The parent directory is in the trash too, restoring recreates it
+        assert_eq!(trash.restore(&entry.id, false).unwrap(), entry.original);
+        assert_eq!(text(&dir.join("docs/note.txt")), "first");
+        assert!(trash.restore(&entries[1].id, false).is_err());
+        trash.restore(&entries[1].id, true).unwrap();
+        assert_eq!(text(&dir.join("docs/note.txt")), "second");
+
+        assert_eq!(trash.restore("nope", false).unwrap_err().to_string(), "Trash entry not found: nope");
+
+        assert_eq!(trash.empty().unwrap(), 1);
+        assert!(trash.list().unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_symlinks_when_copying_and_moving() {
+        use std::os::unix::fs::symlink;
+        let dir = test_dir("manage-links");
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("elsewhere")).unwrap();
+        fs::write(dir.join("src/a.txt"), "a").unwrap();
+        symlink("a.txt", dir.join("src/link")).unwrap();
+
+        copy(&path("src"), &path("copy"), false).unwrap();
+        assert_eq!(fs::read_link(dir.join("copy/link")).unwrap(), Path::new("a.txt"));
+        // What rename falls back to across filesystems
+        move_by_copy(&dir.join("copy"), &dir.join("moved")).unwrap();
+        assert!(!dir.join("copy").exists());
+        assert_eq!(fs::read_link(dir.join("moved/link")).unwrap(), Path::new("a.txt"));
+
+        // A link to a directory is a file to replace, not a directory to copy into
+        symlink(dir.join("elsewhere"), dir.join("dirlink")).unwrap();
+        assert!(copy(&path("src/a.txt"), &path("dirlink"), false).is_err());
+        copy(&path("src/a.txt"), &path("dirlink"), true).unwrap();
+        assert_eq!(text(&dir.join("dirlink")), "a");
+        assert!(fs::read_dir(dir.join("elsewhere")).unwrap().next().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
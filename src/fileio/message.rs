@@ -0,0 +1,59 @@
+use std::io::{self, BufRead};
+
+// A line containing only this ends multi-line input, like in mail
+pub const SENTINEL: &str = ".";
+
+// Reads lines until one equals `sentinel` or the input ends. The sentinel line itself is dropped,
+// every other line keeps its newline.
+pub fn read_until_sentinel<R: BufRead>(reader: &mut R, sentinel: &str) -> io::Result<String> {
+    let mut message = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim_end_matches(['\n', '\r']) == sentinel {
+            break;
+        }
+        message.push_str(&line);
+    }
+    Ok(message)
+}
+
+// The first `max_lines` lines of `message` and a summary of the rest, for confirming before a write
+pub fn preview(message: &str, max_lines: usize) -> String {
+    let total = message.lines().count();
+    let mut preview = String::new();
+    for line in message.lines().take(max_lines) {
+        preview.push_str("  | ");
+        preview.push_str(line);
+        preview.push('\n');
+    }
+    if total > max_lines {
+        preview.push_str(&format!("  | ... {} more lines\n", total - max_lines));
+    }
+    let noun = if total == 1 { "line" } else { "lines" };
+    preview.push_str(&format!("{} {}, {} bytes", total, noun, message.len()));
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_sentinel_or_eof() {
+        let mut input = "first\nsecond\r\n.\nnot read\n".as_bytes();
+        assert_eq!(read_until_sentinel(&mut input, SENTINEL).unwrap(), "first\nsecond\r\n");
+        assert_eq!(read_until_sentinel(&mut input, SENTINEL).unwrap(), "not read\n");
+        assert_eq!(read_until_sentinel(&mut input, SENTINEL).unwrap(), "");
+    }
+
+    #[test]
+    fn previews_long_messages() {
+        let message = "a\nb\nc\nd\n";
+        assert_eq!(preview(message, 2), "  | a\n  | b\n  | ... 2 more lines\n4 lines, 8 bytes");
+        assert_eq!(preview("one", 5), "  | one\n1 line, 3 bytes");
+    }
+}
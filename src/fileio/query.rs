@@ -0,0 +1,459 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use crate::apperror::AppError;
+
+// A small SQL-like query over a CSV table whose first record is the header:
+//     select <columns | *> [where <condition> [and <condition>]...] [group by <column>]
+//         [order by <column> [asc | desc]] [limit <n>]
+// Columns may be plain names or quoted, and in the select list also count, sum(column) or
+// avg(column). Conditions are `column op value` with op one of = != < <= > >= ~ (contains,
+// ignoring case). Values that both look like numbers are compared as numbers.
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    select: Vec<Item>,
+    filters: Vec<Filter>,
+    group_by: Option<String>,
+    order_by: Option<(String, bool)>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Item {
+    All,
+    Column(String),
+    Count,
+    Sum(String),
+    Avg(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct Filter {
+    column: String,
+    op: String,
+    value: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Symbol(String),
+}
+
+const OPERATORS: [&str; 9] = ["=", "==", "!=", "<>", "<", "<=", ">", ">=", "~"];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ',' || c == '(' || c == ')' {
+            chars.next();
+            tokens.push(Token::Symbol(c.to_string()));
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut quoted = String::new();
+            loop {
+                match chars.next() {
+                    Some(q) if q == c => break,
+                    Some(other) => quoted.push(other),
+                    None => return Err(format!("missing closing {}", c)),
+                }
+            }
+            tokens.push(Token::Quoted(quoted));
+        } else if "=!<>~".contains(c) {
+            let mut op = String::new();
+            while let Some(&c) = chars.peek().filter(|c| "=!<>~".contains(**c)) {
+                op.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Symbol(op));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| !c.is_whitespace() && !",()'\"=!<>~".contains(**c)) {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}'", keyword))
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.tokens.get(self.position), Some(Token::Symbol(s)) if s == symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    // A column name or a value, bare or quoted
+    fn name(&mut self, what: &str) -> Result<String, String> {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word) | Token::Quoted(word)) => {
+                self.position += 1;
+                Ok(word.clone())
+            }
+            _ => Err(format!("expected {}", what)),
+        }
+    }
+
+    fn item(&mut self) -> Result<Item, String> {
+        let word = self.name("a column")?;
+        let function = word.to_ascii_lowercase();
+        if !matches!(function.as_str(), "count" | "sum" | "avg") || !self.symbol("(") {
+            return Ok(match (word.as_str(), function.as_str()) {
+                ("*", _) => Item::All,
+                (_, "count") => Item::Count,
+                _ => Item::Column(word),
+            });
+        }
+        let argument = self.name("a column")?;
+        if !self.symbol(")") {
+            return Err(format!("expected ')' after {}({}", function, argument));
+        }
+        match function.as_str() {
+            "count" => Ok(Item::Count),
+            "sum" => Ok(Item::Sum(argument)),
+            _ => Ok(Item::Avg(argument)),
+        }
+    }
+
+    fn filter(&mut self) -> Result<Filter, String> {
+        let column = self.name("a column")?;
+        let op = match self.tokens.get(self.position) {
+            Some(Token::Symbol(op)) if OPERATORS.contains(&op.as_str()) => op.clone(),
+            _ => return Err(format!("expected one of {} after {}", OPERATORS.join(" "), column)),
+        };
+        self.position += 1;
+        let value = self.name("a value")?;
+        Ok(Filter { column, op, value })
+    }
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, AppError> {
+        Query::parse_text(text).map_err(|message| AppError::invalid("query", message))
+    }
+
+    fn parse_text(text: &str) -> Result<Query, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+        parser.expect_keyword("select")?;
+        let mut query = Query {
+            select: vec![parser.item()?],
+            filters: Vec::new(),
+            group_by: None,
+            order_by: None,
+            limit: None,
+        };
+        while parser.symbol(",") {
+            query.select.push(parser.item()?);
+        }
+        if parser.keyword("where") {
+            query.filters.push(parser.filter()?);
+            while parser.keyword("and") {
+                query.filters.push(parser.filter()?);
+            }
+        }
+        if parser.keyword("group") {
+            parser.expect_keyword("by")?;
+            query.group_by = Some(parser.name("a column")?);
+        }
+        if parser.keyword("order") {
+            parser.expect_keyword("by")?;
+            let column = parser.name("a column")?;
+            let descending = parser.keyword("desc");
+            if !descending {
+                parser.keyword("asc");
+            }
+            query.order_by = Some((column, descending));
+        }
+        if parser.keyword("limit") {
+            let count = parser.name("a number")?;
+            query.limit = Some(count.parse().map_err(|_| format!("'{}' is not a valid limit", count))?);
+        }
+        match parser.tokens.get(parser.position) {
+            None => Ok(query),
+            Some(Token::Word(word) | Token::Quoted(word) | Token::Symbol(word)) => {
+                Err(format!("unexpected '{}'", word))
+            }
+        }
+    }
+
+    // Runs the query over `records`, the first of which is the header. The result has a header too.
+    pub fn run(&self, records: &[Vec<String>]) -> Result<Vec<Vec<String>>, AppError> {
+        self.run_rows(records).map_err(|message| AppError::invalid("query", message))
+    }
+
+    fn run_rows(&self, records: &[Vec<String>]) -> Result<Vec<Vec<String>>, String> {
+        let (header, rows) = match records.split_first() {
+            Some(split) => split,
+            None => return Err(String::from("the file has no header")),
+        };
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|h| h == name)
+                .or_else(|| header.iter().position(|h| h.eq_ignore_ascii_case(name)))
+                .ok_or_else(|| format!("no column named '{}'", name))
+        };
+
+        let mut filters = Vec::with_capacity(self.filters.len());
+        for filter in &self.filters {
+            filters.push((column(&filter.column)?, filter));
+        }
+        let mut rows: Vec<&Vec<String>> = rows
+            .iter()
+            .filter(|row| filters.iter().all(|(index, filter)| matches(cell(row, *index), &filter.op, &filter.value)))
+            .collect();
+
+        let aggregated = self.group_by.is_some()
+            || self.select.iter().any(|item| matches!(item, Item::Count | Item::Sum(_) | Item::Avg(_)));
+        let mut output = if aggregated {
+            self.aggregate(&rows, &column)?
+        } else {
+            // Without grouping, rows can be sorted by any column, selected or not
+            if let Some((name, descending)) = &self.order_by {
+                let index = column(name)?;
+                rows.sort_by(|a, b| order(compare(cell(a, index), cell(b, index)), *descending));
+            }
+            let mut indices = Vec::new();
+            let mut names = Vec::new();
+            for item in &self.select {
+                match item {
+                    Item::All => {
+                        indices.extend(0..header.len());
+                        names.extend(header.iter().cloned());
+                    }
+                    Item::Column(name) => {
+                        indices.push(column(name)?);
+                        names.push(name.clone());
+                    }
+                    _ => unreachable!("aggregates are handled above"),
+                }
+            }
+            let mut output = vec![names];
+            output.extend(rows.iter().map(|row| indices.iter().map(|&i| cell(row, i).to_string()).collect()));
+            output
+        };
+
+        if let Some(limit) = self.limit {
+            output.truncate(limit + 1);
+        }
+        Ok(output)
+    }
+
+    fn aggregate<C>(&self, rows: &[&Vec<String>], column: &C) -> Result<Vec<Vec<String>>, String>
+    where
+        C: Fn(&str) -> Result<usize, String>,
+    {
+        let group_index = match &self.group_by {
+            Some(name) => Some(column(name)?),
+            None => None,
+        };
+
+        // Groups keep the order in which their first row appeared
+        let mut groups: Vec<(String, Vec<&Vec<String>>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        match group_index {
+            Some(index) => {
+                for row in rows {
+                    let key = cell(row, index).to_string();
+                    let position = *positions.entry(key.clone()).or_insert_with(|| {
+                        groups.push((key, Vec::new()));
+                        groups.len() - 1
+                    });
+                    groups[position].1.push(row);
+                }
+            }
+            None => groups.push((String::new(), rows.to_vec())),
+        }
+
+        let mut names = Vec::new();
+        for item in &self.select {
+            names.push(match item {
+                Item::All => return Err(String::from("* can not be combined with group by or count/sum/avg")),
+                Item::Column(name) => {
+                    if group_index != Some(column(name)?) {
+                        return Err(format!("'{}' must be the group by column or inside count/sum/avg", name));
+                    }
+                    name.clone()
+                }
+                Item::Count => String::from("count"),
+                Item::Sum(name) => format!("sum({})", name),
+                Item::Avg(name) => format!("avg({})", name),
+            });
+        }
+
+        let mut output = Vec::with_capacity(groups.len());
+        for (key, members) in &groups {
+            let mut row = Vec::with_capacity(self.select.len());
+            for item in &self.select {
+                row.push(match item {
+                    Item::Column(_) => key.clone(),
+                    Item::Count => members.len().to_string(),
+                    Item::Sum(name) | Item::Avg(name) => {
+                        let index = column(name)?;
+                        let mut total = 0.0;
+                        let mut count = 0;
+                        for member in members {
+                            // Empty cells are missing values, not zeros
+                            let value = cell(member, index).trim();
+                            if value.is_empty() {
+                                continue;
+                            }
+                            total += value
+                                .parse::<f64>()
+                                .map_err(|_| format!("'{}' in column '{}' is not a number", value, name))?;
+                            count += 1;
+                        }
+                        match item {
+                            Item::Sum(_) => format_number(total),
+                            _ if count == 0 => String::new(),
+                            _ => format_number(total / count as f64),
+                        }
+                    }
+                    Item::All => unreachable!("rejected above"),
+                });
+            }
+            output.push(row);
+        }
+
+        // Grouped results are sorted by one of their own columns, e.g. "order by count desc"
+        if let Some((name, descending)) = &self.order_by {
+            let index = names
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("can only order grouped results by a selected column, not '{}'", name))?;
+            output.sort_by(|a, b| order(compare(&a[index], &b[index]), *descending));
+        }
+        output.insert(0, names);
+        Ok(output)
+    }
+}
+
+// Short rows are padded with empty cells
+fn cell(row: &[String], index: usize) -> &str {
+    row.get(index).map_or("", String::as_str)
+}
+
+// Numbers compare as numbers, everything else as text
+fn compare(a: &str, b: &str) -> Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+fn order(ordering: Ordering, descending: bool) -> Ordering {
+    if descending {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+fn matches(value: &str, op: &str, expected: &str) -> bool {
+    let ordering = compare(value, expected);
+    match op {
+        "=" | "==" => ordering == Ordering::Equal,
+        "!=" | "<>" => ordering != Ordering::Equal,
+        "<" => ordering == Ordering::Less,
+        "<=" => ordering != Ordering::Greater,
+        ">" => ordering == Ordering::Greater,
+        ">=" => ordering != Ordering::Less,
+        _ => value.to_lowercase().contains(&expected.to_lowercase()),
+    }
+}
+
+// Whole numbers without a fraction, others with at most 4 decimals
+fn format_number(value: f64) -> String {
+    let text = format!("{:.4}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        String::from("0")
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Vec<Vec<String>> {
+        let text = "name,city,age\nAlice,Oslo,30\nBob,Bergen,25\nCarol,Oslo,41\nDan,Bergen,\nEve,\"New York\",35\n";
+        crate::fileio::parse_csv("people.csv", text, ',').unwrap()
+    }
+
+    fn run(query: &str) -> Vec<String> {
+        let result = Query::parse(query).unwrap().run(&table()).unwrap();
+        result.iter().map(|row| row.join(",")).collect()
+    }
+
+    #[test]
+    fn selects_filters_and_sorts() {
+        assert_eq!(
+            run("select name, age where age >= 30 order by age desc"),
+            ["name,age", "Carol,41", "Eve,35", "Alice,30"]
+        );
+        assert_eq!(run("SELECT * WHERE city ~ 'york'"), ["name,city,age", "Eve,New York,35"]);
+        assert_eq!(run("select name where city = Oslo and age < 40"), ["name", "Alice"]);
+        assert_eq!(run("select name order by name desc limit 2"), ["name", "Eve", "Dan"]);
+    }
+
+    #[test]
+    fn groups_and_aggregates() {
+        assert_eq!(
+            run("select city, count, sum(age), avg(age) group by city order by count desc"),
+            ["city,count,sum(age),avg(age)", "Oslo,2,71,35.5", "Bergen,2,25,25", "New York,1,35,35"]
+        );
+        assert_eq!(run("select count(*), avg(age)"), ["count,avg(age)", "5,32.75"]);
+    }
+
+    #[test]
+    fn reports_mistakes() {
+        let error = |query: &str| match Query::parse(query) {
+            Ok(query) => query.run(&table()).err().unwrap(),
+            Err(e) => e,
+        }
+        .to_string();
+        assert_eq!(error("name, age"), "Invalid query: expected 'select'");
+        assert_eq!(error("select salary"), "Invalid query: no column named 'salary'");
+        assert_eq!(
+            error("select name, count group by city"),
+            "Invalid query: 'name' must be the group by column or inside count/sum/avg"
+        );
+        assert_eq!(error("select sum(name)"), "Invalid query: 'Alice' in column 'name' is not a number");
+        assert_eq!(error("select name where age"), "Invalid query: expected one of = == != <> < <= > >= ~ after age");
+        assert_eq!(error("select name limit 3 extra"), "Invalid query: unexpected 'extra'");
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+use crate::apperror::AppError;
+
+// Optional directory that every path handed to fileio has to stay inside. None means no limit.
+static ROOT: RwLock<Option<Sandbox>> = RwLock::new(None);
+
+pub struct Sandbox {
+    // Canonical, so comparing prefixes is enough to tell inside from outside
+    root: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new(root: &str) -> Result<Sandbox, AppError> {
+        let canonical = fs::canonicalize(root).map_err(|e| AppError::io("open root directory", root, e))?;
+        if !canonical.is_dir() {
+            let e = std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a directory");
+            return Err(AppError::io("use as root directory", root, e));
+        }
+        Ok(Sandbox { root: canonical })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Relative paths start at the root, absolute ones have to point into it. `..` is resolved
+    // by hand first, then the longest part that exists is canonicalised so a symlink pointing out
+    // of the root is caught too. What canonicalize can not resolve is either missing (a new file)
+    // or a dangling link, which would be followed on create, so those are refused.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, AppError> {
+        let requested = Path::new(path);
+        let joined = if requested.is_absolute() { requested.to_path_buf() } else { self.root.join(requested) };
+
+        let mut normal = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normal.pop();
+                }
+                other => normal.push(other),
+            }
+        }
+        if !normal.starts_with(&self.root) {
+            return Err(self.outside(path, false));
+        }
+
+        let mut existing = normal.as_path();
+        let mut missing = Vec::new();
+        let mut real = loop {
+            match fs::canonicalize(existing) {
+                Ok(real) => break real,
+                Err(_) if fs::symlink_metadata(existing).is_ok_and(|meta| meta.file_type().is_symlink()) => {
+                    return Err(self.outside(path, true));
+                }
+                Err(_) => match (existing.file_name(), existing.parent()) {
+                    (Some(name), Some(parent)) => {
+                        missing.push(name);
+                        existing = parent;
+                    }
+                    // Only reachable if the root itself disappeared
+                    _ => return Err(self.outside(path, false)),
+                },
+            }
+        };
+        if !real.starts_with(&self.root) {
+            return Err(self.outside(path, true));
+        }
+        real.extend(missing.iter().rev());
+        Ok(real)
+    }
+
+    fn outside(&self, path: &str, through_link: bool) -> AppError {
+        AppError::OutsideRoot {
+            path: path.to_string(),
+            root: self.root.to_string_lossy().into_owned(),
+            through_link,
+        }
+    }
+}
+
+// Limits all later file operations to `dir`
+pub fn set_root(dir: &str) -> Result<(), AppError> {
+    let sandbox = Sandbox::new(dir)?;
+    *ROOT.write().unwrap_or_else(|e| e.into_inner()) = Some(sandbox);
+    Ok(())
+}
+
+pub fn root() -> Option<PathBuf> {
+    let root = ROOT.read().unwrap_or_else(|e| e.into_inner());
+    root.as_ref().map(|sandbox| sandbox.root().to_path_buf())
+}
+
+// The path to actually open for `path`: unchanged without a root, otherwise checked against it.
+// Every fileio function that touches the disk goes through this first.
+pub fn resolve(path: &str) -> Result<String, AppError> {
+    let root = ROOT.read().unwrap_or_else(|e| e.into_inner());
+    match root.as_ref() {
+        Some(sandbox) => sandbox.resolve(path).map(|resolved| resolved.to_string_lossy().into_owned()),
+        None => Ok(path.to_string()),
+    }
+}
+
+// Path::exists for paths that are subject to the root, anything outside it does not exist
+pub fn exists(path: &str) -> bool {
+    resolve(path).is_ok_and(|resolved| Path::new(&resolved).exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fileio::test_dir;
+
+    fn setup(name: &str) -> (PathBuf, Sandbox) {
+        let dir = test_dir(name);
+        fs::create_dir_all(dir.join("root/docs")).unwrap();
+        fs::write(dir.join("root/docs/a.txt"), "inside").unwrap();
+        fs::write(dir.join("secret.txt"), "outside").unwrap();
+        let sandbox = Sandbox::new(dir.join("root").to_str().unwrap()).unwrap();
+        (dir, sandbox)
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_root() {
+        let (dir, sandbox) = setup("sandbox-inside");
+        let root = sandbox.root().to_path_buf();
+        assert_eq!(sandbox.resolve("docs/a.txt").unwrap(), root.join("docs/a.txt"));
+        assert_eq!(sandbox.resolve("./docs/../docs/a.txt").unwrap(), root.join("docs/a.txt"));
+        // New files and directories are fine as long as they would end up inside
+        assert_eq!(sandbox.resolve("new/dir/b.txt").unwrap(), root.join("new/dir/b.txt"));
+        assert_eq!(sandbox.resolve(root.join("docs").to_str().unwrap()).unwrap(), root.join("docs"));
+        assert_eq!(sandbox.resolve(".").unwrap(), root);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_traversal_and_absolute_paths() {
+        let (dir, sandbox) = setup("sandbox-traversal");
+        for path in ["../secret.txt", "docs/../../secret.txt", "../../../../etc/passwd", "/etc/passwd"] {
+            let error = sandbox.resolve(path).err().unwrap();
+            assert_eq!(
+                error.to_string(),
+                format!("{} is outside the allowed directory {}", path, sandbox.root().display())
+            );
+        }
+        // A sibling whose name merely starts with the root's name is outside too
+        fs::create_dir_all(dir.join("root2")).unwrap();
+        assert!(sandbox.resolve(dir.join("root2").to_str().unwrap()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_escapes() {
+        use std::os::unix::fs::symlink;
+        let (dir, sandbox) = setup("sandbox-symlink");
+        symlink(dir.join("secret.txt"), dir.join("root/leak.txt")).unwrap();
+        symlink(&dir, dir.join("root/up")).unwrap();
+        symlink(dir.join("root/docs"), dir.join("root/shortcut")).unwrap();
+
+        let error = sandbox.resolve("leak.txt").err().unwrap();
+        assert!(error.to_string().contains("symbolic link"), "{}", error);
+        assert!(sandbox.resolve("up/secret.txt").is_err());
+        assert!(sandbox.resolve("up/new-file.txt").is_err());
+        // A dangling link would be followed when the file is created
+        symlink(dir.join("pwned.txt"), dir.join("root/evil")).unwrap();
+        let error = sandbox.resolve("evil").err().unwrap();
+        assert!(error.to_string().contains("symbolic link"), "{}", error);
+        symlink(dir.join("missing/dir"), dir.join("root/gone")).unwrap();
+        assert!(sandbox.resolve("gone/new.txt").is_err());
+        // Links that stay inside the root are followed
+        assert_eq!(sandbox.resolve("shortcut/a.txt").unwrap(), sandbox.root().join("docs/a.txt"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use log::warn;
+use regex::{Regex, RegexBuilder};
+use crate::apperror::AppError;
+use super::{detect_file_encoding, for_each_line, read_decoded, Encoding, InvalidUtf8, LineSelection};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchOptions {
+    // Treat the pattern as a regular expression instead of literal text
+    pub regex: bool,
+    pub ignore_case: bool,
+    // Only match the pattern as a whole word
+    pub whole_word: bool,
+    // Lines shown before and after every match
+    pub context: usize,
+}
+
+pub fn build_matcher(pattern: &str, options: SearchOptions) -> Result<Regex, regex::Error> {
+    let mut pattern = if options.regex { pattern.to_string() } else { regex::escape(pattern) };
+    if options.whole_word {
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+    RegexBuilder::new(&pattern).case_insensitive(options.ignore_case).build()
+}
+
+// Searches a file, or every text file below a directory, and reports grep-style lines to `on_line`:
+//     path:12:matching line
+//     path-13-context line
+//     --            between groups that are not next to each other
+// Hidden files and directories are skipped when walking a tree, binary files always.
+// Returns the number of matching lines.
+pub fn search<F>(path: &str, matcher: &Regex, context: usize, mut on_line: F) -> Result<usize, AppError>
+where
+    F: FnMut(&str) -> bool,
+{
+    let resolved = super::resolve(path)?;
+    let metadata = fs::metadata(&resolved).map_err(|e| AppError::io("open", path, e))?;
+    let mut searcher = Searcher {
+        matcher,
+        context,
+        matches: 0,
+        stopped: false,
+        printed_any: false,
+    };
+    if metadata.is_dir() {
+        // Below the starting directory symlinks are skipped, so the walk can not leave the root
+        searcher.search_dir(Path::new(&resolved), &mut on_line)?;
+    } else {
+        searcher.search_file(path, &mut on_line)?;
+    }
+    Ok(searcher.matches)
+}
+
+struct Searcher<'a> {
+    matcher: &'a Regex,
+    context: usize,
+    matches: usize,
+    stopped: bool,
+    printed_any: bool,
+}
+
+impl Searcher<'_> {
+    fn search_dir<F>(&mut self, dir: &Path, on_line: &mut F) -> Result<(), AppError>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let shown = dir.to_string_lossy();
+        let mut entries: Vec<_> = fs::read_dir(dir)
+            .map_err(|e| AppError::io("list", shown.as_ref(), e))?
+            .filter_map(|entry| entry.ok())
+            .collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            if self.stopped {
+                break;
+            }
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            // Symlinks are not followed, a link back up the tree would never finish
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+            let result = if file_type.is_dir() {
+                self.search_dir(&path, on_line)
+            } else if file_type.is_file() {
+                self.search_file(&path.to_string_lossy(), on_line)
+            } else {
+                Ok(())
+            };
+            // One unreadable file should not end the whole search
+            if let Err(e) = result {
+                warn!("{:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    fn search_file<F>(&mut self, path: &str, on_line: &mut F) -> Result<(), AppError>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        // Binary files are skipped, the other encodings are decoded like read and convert do
+        let encoding = match detect_file_encoding(path)? {
+            Some(encoding) => encoding,
+            None => return Ok(()),
+        };
+
+        let mut before: VecDeque<(usize, String)> = VecDeque::new();
+        let mut after = 0;
+        let mut last_shown = 0;
+
+        let mut visit = |number: usize, line: &str| {
+            if self.matcher.is_match(line) {
+                self.matches += 1;
+                let first = before.front().map_or(number, |(n, _)| *n);
+                if self.printed_any && (last_shown == 0 || first > last_shown + 1) && !on_line("--") {
+                    self.stopped = true;
+                    return false;
+                }
+                for (n, text) in before.drain(..) {
+                    if !on_line(&format!("{}-{}-{}", path, n, text)) {
+                        self.stopped = true;
+                        return false;
+                    }
+                }
+                self.printed_any = true;
+                last_shown = number;
+                after = self.context;
+                if !on_line(&format!("{}:{}:{}", path, number, line)) {
+                    self.stopped = true;
+                    return false;
+                }
+            } else if after > 0 {
+                after -= 1;
+                last_shown = number;
+                if !on_line(&format!("{}-{}-{}", path, number, line)) {
+                    self.stopped = true;
+                    return false;
+                }
+            } else if self.context > 0 {
+                if before.len() == self.context {
+                    before.pop_front();
+                }
+                before.push_back((number, line.to_string()));
+            }
+            true
+        };
+        // Plain UTF-8 is streamed, the rest has to be decoded as a whole
+        if encoding == Encoding::Utf8 {
+            return for_each_line(path, LineSelection::All, InvalidUtf8::Lossy, visit);
+        }
+        let (text, _) = read_decoded(path)?;
+        for (index, line) in text.lines().enumerate() {
+            if !visit(index + 1, line) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fileio::test_dir;
+
+    fn run(path: &str, pattern: &str, options: SearchOptions) -> (usize, Vec<String>) {
+        let matcher = build_matcher(pattern, options).unwrap();
+        let mut lines = Vec::new();
+        let count = search(path, &matcher, options.context, |line| {
+            lines.push(line.to_string());
+            true
+        })
+        .unwrap();
+        (count, lines)
+    }
+
+    #[test]
+    fn searches_a_tree_with_options() {
+        let dir = test_dir("search");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("a.txt"), "Port = 80\nport_range = 1\n").unwrap();
+        fs::write(dir.join("sub/b.conf"), "export PORT=8080\n").unwrap();
+        fs::write(dir.join(".git/config"), "port\n").unwrap();
+        fs::write(dir.join("image.bin"), [b'p', b'o', b'r', b't', 0]).unwrap();
+        let root = dir.to_str().unwrap();
+
+        // "export" contains "port" too
+        let (count, _) = run(root, "port", SearchOptions::default());
+        assert_eq!(count, 2);
+
+        let options = SearchOptions { ignore_case: true, ..Default::default() };
+        assert_eq!(run(root, "port", options).0, 3);
+
+        let options = SearchOptions { ignore_case: true, whole_word: true, ..Default::default() };
+        let (count, lines) = run(root, "port", options);
+        assert_eq!(count, 2);
+        assert!(lines[0].ends_with("a.txt:1:Port = 80"));
+        assert_eq!(lines[1], "--");
+        assert!(lines[2].ends_with("b.conf:1:export PORT=8080"));
+
+        let options = SearchOptions { regex: true, ..Default::default() };
+        assert_eq!(run(root, r"=\s*\d+$", options).0, 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn shows_context_and_separators() {
+        let dir = test_dir("search-context");
+        let path = dir.join("log.txt");
+        let text: String = (1..=9)
+            .map(|n| if n == 2 || n == 8 { format!("hit {}\n", n) } else { format!("{}\n", n) })
+            .collect();
+        fs::write(&path, text).unwrap();
+        let path = path.to_str().unwrap();
+
+        let options = SearchOptions { context: 1, ..Default::default() };
+        let (count, lines) = run(path, "hit", options);
+        assert_eq!(count, 2);
+        let expected = [
+            format!("{}-1-1", path),
+            format!("{}:2:hit 2", path),
+            format!("{}-3-3", path),
+            String::from("--"),
+            format!("{}-7-7", path),
+            format!("{}:8:hit 8", path),
+            format!("{}-9-9", path),
+        ];
+        assert_eq!(lines, expected);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn decodes_other_encodings() {
+        let dir = test_dir("search-encodings");
+        let wide: Vec<u8> = [0xFF, 0xFE].into_iter().chain("caf\u{e9} port\n".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        fs::write(dir.join("wide.txt"), wide).unwrap();
+        fs::write(dir.join("latin.txt"), b"caf\xE9 port\n").unwrap();
+        let (count, lines) = run(dir.to_str().unwrap(), "caf\u{e9}", SearchOptions::default());
+        assert_eq!(count, 2);
+        assert!(lines.iter().filter(|line| *line != "--").all(|line| line.ends_with(":1:caf\u{e9} port")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use crate::apperror::AppError;
+use super::{format_table, resolve};
+
+// Counts for one file, or several added together. Lines are counted like for_each_line sees
+// them, so a last line without a line break still counts. Characters include the line breaks,
+// like wc -m.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextStats {
+    pub lines: u64,
+    pub words: u64,
+    pub chars: u64,
+    pub bytes: u64,
+    // In characters, without the line break
+    pub longest_line: u64,
+    // 1-based, 0 for an empty file
+    pub longest_line_number: u64,
+    // Characters in all lines without their line breaks, for the average
+    line_chars: u64,
+    word_counts: HashMap<String, u64>,
+}
+
+impl TextStats {
+    fn add_line(&mut self, line: &[u8]) {
+        self.lines += 1;
+        self.bytes += line.len() as u64;
+        let text = String::from_utf8_lossy(line);
+        self.chars += text.chars().count() as u64;
+
+        let content = text.strip_suffix('\n').unwrap_or(&text);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+        let length = content.chars().count() as u64;
+        self.line_chars += length;
+        if length > self.longest_line || self.longest_line_number == 0 {
+            self.longest_line = length;
+            self.longest_line_number = self.lines;
+        }
+
+        for word in content.split_whitespace() {
+            self.words += 1;
+            // "The", "the" and "the," are the same word for the frequency list
+            let normal = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+            if !normal.is_empty() {
+                *self.word_counts.entry(normal).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn average_line_length(&self) -> f64 {
+        if self.lines == 0 { 0.0 } else { self.line_chars as f64 / self.lines as f64 }
+    }
+
+    // The `count` most frequent words, ties in alphabetical order
+    pub fn top_words(&self, count: usize) -> Vec<(&str, u64)> {
+        let mut words: Vec<(&str, u64)> = self.word_counts.iter().map(|(word, n)| (word.as_str(), *n)).collect();
+        words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        words.truncate(count);
+        words
+    }
+
+    // Adds another file's counts, for a total over several. The longest line number then only
+    // means something together with the file it came from, so it is that of the longest one.
+    pub fn merge(&mut self, other: &TextStats) {
+        self.lines += other.lines;
+        self.words += other.words;
+        self.chars += other.chars;
+        self.bytes += other.bytes;
+        self.line_chars += other.line_chars;
+        if other.longest_line > self.longest_line || self.longest_line_number == 0 {
+            self.longest_line = other.longest_line;
+            self.longest_line_number = other.longest_line_number;
+        }
+        for (word, n) in &other.word_counts {
+            *self.word_counts.entry(word.clone()).or_insert(0) += n;
+        }
+    }
+}
+
+// Reads the file a line at a time, so only the distinct words are kept in memory
+pub fn text_stats(path: &str) -> Result<TextStats, AppError> {
+    let file = File::open(resolve(path)?).map_err(|e| AppError::io("open", path, e))?;
+    let mut reader = BufReader::new(file);
+    let mut stats = TextStats::default();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).map_err(|e| AppError::io("read", path, e))?;
+        if read == 0 {
+            break;
+        }
+        stats.add_line(&line);
+    }
+    Ok(stats)
+}
+
+// The numbers for a single file as "name: value" lines, then the `top` most frequent words
+fn stats_summary(stats: &TextStats, top: usize) -> Vec<String> {
+    let mut lines = vec![
+        format!("lines: {}", stats.lines),
+        format!("words: {}", stats.words),
+        format!("characters: {}", stats.chars),
+        format!("bytes: {}", stats.bytes),
+        format!("longest line: {} characters (line {})", stats.longest_line, stats.longest_line_number),
+        format!("average line: {:.1} characters", stats.average_line_length()),
+    ];
+    lines.extend(top_word_lines(stats, top));
+    lines
+}
+
+// "most frequent words:" and a count and word per line, nothing for a file without words
+fn top_word_lines(stats: &TextStats, top: usize) -> Vec<String> {
+    let words = stats.top_words(top);
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let width = words.iter().map(|(_, n)| n.to_string().len()).max().unwrap_or(0);
+    let mut lines = vec![String::from("most frequent words:")];
+    lines.extend(words.iter().map(|(word, n)| format!("  {:>width$}  {}", n, word, width = width)));
+    lines
+}
+
+// One row per file for format_table, with a total row when there is more than one
+fn stats_table(files: &[(&str, &TextStats)]) -> Vec<Vec<String>> {
+    let header = ["file", "lines", "words", "chars", "bytes", "longest", "avg line"];
+    let mut rows = vec![header.iter().map(|name| name.to_string()).collect()];
+    let row = |name: &str, stats: &TextStats| {
+        vec![
+            name.to_string(),
+            stats.lines.to_string(),
+            stats.words.to_string(),
+            stats.chars.to_string(),
+            stats.bytes.to_string(),
+            stats.longest_line.to_string(),
+            format!("{:.1}", stats.average_line_length()),
+        ]
+    };
+    let mut total = TextStats::default();
+    for (name, stats) in files {
+        rows.push(row(name, stats));
+        total.merge(stats);
+    }
+    if files.len() > 1 {
+        rows.push(row("total", &total));
+    }
+    rows
+}
+
+// What the stats command shows: the summary for a single file, or a table comparing several
+// followed by their most frequent words together
+pub fn stats_report<S: AsRef<str>>(files: &[(S, TextStats)], top: usize) -> Vec<String> {
+    if let [(_, stats)] = files {
+        return stats_summary(stats, top);
+    }
+    let rows: Vec<(&str, &TextStats)> = files.iter().map(|(name, stats)| (name.as_ref(), stats)).collect();
+    let mut lines = format_table(&stats_table(&rows));
+    let mut total = TextStats::default();
+    for (_, stats) in files {
+        total.merge(stats);
+    }
+    let words = top_word_lines(&total, top);
+    if !words.is_empty() {
+        lines.push(String::new());
+        lines.extend(words);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::fileio::test_dir;
+
+    #[test]
+    fn counts_like_wc() {
+        let dir = test_dir("stats");
+        let path = dir.join("text.txt");
+        fs::write(&path, "The cat, the dog.\r\n\ncaf\u{e9} THE end").unwrap();
+        let stats = text_stats(path.to_str().unwrap()).unwrap();
+
+        assert_eq!((stats.lines, stats.words, stats.chars, stats.bytes), (3, 7, 32, 33));
+        assert_eq!((stats.longest_line, stats.longest_line_number), (17, 1));
+        assert!((stats.average_line_length() - 29.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.top_words(2), [("the", 3), ("caf\u{e9}", 1)]);
+
+        let empty = dir.join("empty.txt");
+        fs::write(&empty, "").unwrap();
+        let none = text_stats(empty.to_str().unwrap()).unwrap();
+        assert_eq!(none, TextStats::default());
+        assert_eq!(none.average_line_length(), 0.0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn adds_a_total_row() {
+        let mut one = TextStats::default();
+        one.add_line(b"a b\n");
+        let mut two = TextStats::default();
+        two.add_line(b"a longer line\n");
+        let rows = stats_table(&[("one", &one), ("two", &two)]);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[3], ["total", "2", "5", "18", "18", "13", "8.0"]);
+    }
+}
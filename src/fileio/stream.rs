@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use crate::apperror::AppError;
+
+// Which lines of a file to show. Line numbers are 1-based and ranges inclusive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineSelection {
+    All,
+    Head(usize),
+    Tail(usize),
+    Range(usize, usize),
+}
+
+// What to do with bytes that are not valid UTF-8
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvalidUtf8 {
+    // Replace them with U+FFFD
+    Lossy,
+    // Show them as \xNN escapes
+    Hex,
+}
+
+impl LineSelection {
+    // Accepts "", "all", "head N", "tail N" and "A-B"
+    pub fn parse(text: &str) -> Option<LineSelection> {
+        let text = text.trim();
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            [] | ["all"] => Some(LineSelection::All),
+            ["head", n] => n.parse().ok().map(LineSelection::Head),
+            ["tail", n] => n.parse().ok().map(LineSelection::Tail),
+            [range] => {
+                let (start, end) = range.split_once('-')?;
+                let start: usize = start.parse().ok()?;
+                let end: usize = end.parse().ok()?;
+                if start == 0 || end < start {
+                    return None;
+                }
+                Some(LineSelection::Range(start, end))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn decode_line(bytes: &[u8], invalid: InvalidUtf8) -> Cow<'_, str> {
+    match invalid {
+        InvalidUtf8::Lossy => String::from_utf8_lossy(bytes),
+        InvalidUtf8::Hex => match std::str::from_utf8(bytes) {
+            Ok(text) => Cow::Borrowed(text),
+            Err(_) => Cow::Owned(escape_invalid(bytes)),
+        },
+    }
+}
+
+fn escape_invalid(mut bytes: &[u8]) -> String {
+    let mut text = String::new();
+    while !bytes.is_empty() {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                text.push_str(valid);
+                break;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                // valid_up_to guarantees this prefix is UTF-8
+                text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                let bad = e.error_len().unwrap_or(rest.len());
+                for byte in &rest[..bad] {
+                    text.push_str(&format!("\\x{:02x}", byte));
+                }
+                bytes = &rest[bad..];
+            }
+        }
+    }
+    text
+}
+
+// Reads `path` one line at a time and calls `on_line(line_number, text)` for every selected line,
+// without trailing newlines. Only the current line (or the last N for Tail) is held in memory.
+// `on_line` returns false to stop early, e.g. when the user quits the pager.
+pub fn for_each_line<F>(
+    path: &str,
+    selection: LineSelection,
+    invalid: InvalidUtf8,
+    mut on_line: F,
+) -> Result<(), AppError>
+where
+    F: FnMut(usize, &str) -> bool,
+{
+    let file = File::open(super::resolve(path)?).map_err(|e| AppError::io("open", path, e))?;
+    let mut reader = BufReader::new(file);
+    let mut buffer = Vec::new();
+    let mut number = 0;
+    let mut tail: VecDeque<(usize, String)> = VecDeque::new();
+
+    loop {
+        buffer.clear();
+        let read = reader
+            .read_until(b'\n', &mut buffer)
+            .map_err(|e| AppError::io("read", path, e))?;
+        if read == 0 {
+            break;
+        }
+        number += 1;
+
+        let line = trim_newline(&buffer);
+        match selection {
+            LineSelection::All => {}
+            LineSelection::Head(n) if number > n => break,
+            LineSelection::Head(_) => {}
+            LineSelection::Range(start, _) if number < start => continue,
+            LineSelection::Range(_, end) if number > end => break,
+            LineSelection::Range(_, _) => {}
+            LineSelection::Tail(n) => {
+                if n > 0 {
+                    if tail.len() == n {
+                        tail.pop_front();
+                    }
+                    tail.push_back((number, decode_line(line, invalid).into_owned()));
+                }
+                continue;
+            }
+        }
+
+        if !on_line(number, &decode_line(line, invalid)) {
+            return Ok(());
+        }
+    }
+
+    for (number, line) in tail {
+        if !on_line(number, &line) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::fileio::test_dir;
+
+    fn collect(path: &str, selection: LineSelection) -> Vec<(usize, String)> {
+        let mut lines = Vec::new();
+        for_each_line(path, selection, InvalidUtf8::Lossy, |number, line| {
+            lines.push((number, line.to_string()));
+            true
+        })
+        .unwrap();
+        lines
+    }
+
+    #[test]
+    fn selects_head_tail_and_ranges() {
+        let dir = test_dir("stream-select");
+        let path = dir.join("lines.txt");
+        let path = path.to_str().unwrap();
+        let text: String = (1..=10).map(|n| format!("line {}\r\n", n)).collect();
+        fs::write(path, text).unwrap();
+
+        let numbers = |lines: Vec<(usize, String)>| lines.into_iter().map(|(n, _)| n).collect::<Vec<_>>();
+        assert_eq!(numbers(collect(path, LineSelection::All)).len(), 10);
+        assert_eq!(numbers(collect(path, LineSelection::Head(3))), [1, 2, 3]);
+        assert_eq!(numbers(collect(path, LineSelection::Tail(2))), [9, 10]);
+        assert_eq!(numbers(collect(path, LineSelection::Range(4, 6))), [4, 5, 6]);
+        assert_eq!(collect(path, LineSelection::Head(1)), [(1, String::from("line 1"))]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parses_selections() {
+        assert_eq!(LineSelection::parse(""), Some(LineSelection::All));
+        assert_eq!(LineSelection::parse("head 5"), Some(LineSelection::Head(5)));
+        assert_eq!(LineSelection::parse("tail 2"), Some(LineSelection::Tail(2)));
+        assert_eq!(LineSelection::parse("3-7"), Some(LineSelection::Range(3, 7)));
+        assert_eq!(LineSelection::parse("7-3"), None);
+        assert_eq!(LineSelection::parse("middle"), None);
+    }
+
+    #[test]
+    fn decodes_invalid_utf8() {
+        let bytes = b"caf\xc3\xa9 \xff\xfe ok";
+        assert_eq!(decode_line(bytes, InvalidUtf8::Lossy), "café \u{fffd}\u{fffd} ok");
+        assert_eq!(decode_line(bytes, InvalidUtf8::Hex), "café \\xff\\xfe ok");
+    }
+}
@@ -0,0 +1,594 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::apperror::AppError;
+use super::{resolve, write_atomic_with, WriteMode};
+
+const BLOCK: usize = 512;
+// Largest size the 11 octal digits of a ustar size field can hold
+const MAX_SIZE: u64 = 0o77777777777;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    // Hard and symbolic links are listed but never created when extracting
+    Link,
+    // Devices, fifos and the like
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TarEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mode: u32,
+    pub modified: SystemTime,
+    // Where a link points, empty for everything else
+    pub link: String,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Octal text padded with NULs or spaces, or GNU's base-256 for values that do not fit
+fn parse_number(field: &[u8]) -> Option<u64> {
+    if field.first().is_some_and(|byte| byte & 0x80 != 0) {
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7F), |value, byte| value.checked_mul(256).map(|v| v + u64::from(*byte)));
+    }
+    let text: String = field
+        .iter()
+        .map(|byte| *byte as char)
+        .take_while(|c| *c != '\0')
+        .collect();
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+fn text_field(field: &[u8]) -> String {
+    let end = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// The checksum is the sum of all header bytes with the checksum field itself counted as spaces.
+// Some old tars summed signed bytes, both are accepted when reading.
+fn checksums(header: &[u8; BLOCK]) -> (u64, i64) {
+    let mut unsigned = 0u64;
+    let mut signed = 0i64;
+    for (index, byte) in header.iter().enumerate() {
+        let byte = if (148..156).contains(&index) { b' ' } else { *byte };
+        unsigned += u64::from(byte);
+        signed += i64::from(byte as i8);
+    }
+    (unsigned, signed)
+}
+
+// Reads entries one at a time. Whatever part of an entry's data the caller did not read is
+// skipped when asking for the next one.
+struct Reader<R: Read> {
+    inner: R,
+    // Bytes read so far, for error messages
+    offset: u64,
+    // Data and padding of the current entry that are still unread
+    data_left: u64,
+    padding_left: u64,
+}
+
+impl<R: Read> Reader<R> {
+    fn new(inner: R) -> Self {
+        Reader { inner, offset: 0, data_left: 0, padding_left: 0 }
+    }
+
+    // False at the end of the input, an error if it ends in the middle of a block
+    fn read_block(&mut self, block: &mut [u8; BLOCK]) -> io::Result<bool> {
+        let mut filled = 0;
+        while filled < BLOCK {
+            match self.inner.read(&mut block[filled..])? {
+                0 if filled == 0 => return Ok(false),
+                0 => return Err(invalid(format!("archive ends in the middle of a block at byte {}", self.offset))),
+                read => filled += read,
+            }
+        }
+        self.offset += BLOCK as u64;
+        Ok(true)
+    }
+
+    fn skip(&mut self, count: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.inner).take(count), &mut io::sink())?;
+        self.offset += skipped;
+        if skipped < count {
+            return Err(invalid(format!("archive ends in the middle of an entry at byte {}", self.offset)));
+        }
+        Ok(())
+    }
+
+    // Copies the rest of the current entry's data to `out`
+    fn copy_data<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let copied = io::copy(&mut (&mut self.inner).take(self.data_left), out)?;
+        self.offset += copied;
+        if copied < self.data_left {
+            return Err(invalid(format!("archive ends in the middle of an entry at byte {}", self.offset)));
+        }
+        self.data_left = 0;
+        Ok(())
+    }
+
+    fn read_data(&mut self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.copy_data(&mut data)?;
+        Ok(data)
+    }
+
+    fn next_entry(&mut self) -> io::Result<Option<TarEntry>> {
+        // Long names from GNU ('L') and pax ('x') headers apply to the entry after them
+        let mut long_name = None;
+        loop {
+            self.skip(self.data_left + self.padding_left)?;
+            self.data_left = 0;
+            self.padding_left = 0;
+
+            let start = self.offset;
+            let mut header = [0u8; BLOCK];
+            // A missing end marker is tolerated, plenty of writers get it wrong
+            if !self.read_block(&mut header)? || header.iter().all(|byte| *byte == 0) {
+                return Ok(None);
+            }
+            let stored = parse_number(&header[148..156]);
+            let (unsigned, signed) = checksums(&header);
+            if stored != Some(unsigned) && stored.map(|value| value as i64) != Some(signed) {
+                return Err(invalid(format!("header checksum mismatch at byte {}", start)));
+            }
+
+            let size = parse_number(&header[124..136])
+                .ok_or_else(|| invalid(format!("invalid size in the header at byte {}", start)))?;
+            self.data_left = size;
+            self.padding_left = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+
+            let kind = match header[156] {
+                b'0' | b'\0' | b'7' => EntryKind::File,
+                b'5' => EntryKind::Dir,
+                b'1' | b'2' => EntryKind::Link,
+                b'3' | b'4' | b'6' => EntryKind::Other,
+                b'L' => {
+                    long_name = Some(text_field(&self.read_data()?));
+                    continue;
+                }
+                b'x' => {
+                    if let Some(path) = pax_path(&self.read_data()?) {
+                        long_name = Some(path);
+                    }
+                    continue;
+                }
+                b'g' => continue,
+                // Unknown vendor types are to be read like regular files, their data is skipped
+                _ => EntryKind::Other,
+            };
+            // Only POSIX ustar has the prefix field, GNU tar uses those bytes for other things
+            let name = text_field(&header[0..100]);
+            let path = match long_name.take() {
+                Some(path) => path,
+                None if &header[257..263] == b"ustar\0" && header[345] != 0 => {
+                    format!("{}/{}", text_field(&header[345..500]), name)
+                }
+                None => name,
+            };
+            // Old tars mark directories only by the trailing slash
+            let kind = if kind == EntryKind::File && path.ends_with('/') { EntryKind::Dir } else { kind };
+            if b"123456".contains(&header[156]) {
+                // Links, devices and directories have no data whatever the size field says
+                self.data_left = 0;
+                self.padding_left = 0;
+            }
+
+            return Ok(Some(TarEntry {
+                path,
+                kind,
+                size,
+                mode: parse_number(&header[100..108]).unwrap_or(0) as u32,
+                modified: UNIX_EPOCH + Duration::from_secs(parse_number(&header[136..148]).unwrap_or(0)),
+                link: text_field(&header[157..257]),
+            }));
+        }
+    }
+}
+
+// Pax records look like "30 path=some/very/long/name\n", the number being the record length
+fn pax_path(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    text.lines()
+        .filter_map(|record| record.split_once(' ').map(|(_, rest)| rest))
+        .find_map(|record| record.strip_prefix("path=").map(str::to_string))
+}
+
+fn open_archive(archive: &str) -> Result<Reader<BufReader<File>>, AppError> {
+    let file = File::open(resolve(archive)?).map_err(|e| AppError::io("open", archive, e))?;
+    Ok(Reader::new(BufReader::new(file)))
+}
+
+// Reports every entry to `on_entry`, which returns false to stop. Returns the number of entries.
+pub fn list_tar<F>(archive: &str, mut on_entry: F) -> Result<usize, AppError>
+where
+    F: FnMut(&TarEntry) -> bool,
+{
+    let mut reader = open_archive(archive)?;
+    let mut count = 0;
+    while let Some(entry) = reader.next_entry().map_err(|e| AppError::io("read", archive, e))? {
+        count += 1;
+        if !on_entry(&entry) {
+            break;
+        }
+    }
+    Ok(count)
+}
+
+// Builds a ustar header. Names longer than 100 bytes are split into the prefix field at a slash.
+fn header(entry: &TarEntry) -> Result<[u8; BLOCK], String> {
+    let mut header = [0u8; BLOCK];
+    let path = entry.path.as_bytes();
+    let (prefix, name) = if path.len() <= 100 {
+        (&path[..0], path)
+    } else {
+        // The name part may end in the slash of a directory, so the split is looked for before that
+        let trimmed = path.len() - usize::from(path.ends_with(b"/"));
+        let split = path[..trimmed]
+            .iter()
+            .enumerate()
+            .filter(|(index, byte)| **byte == b'/' && *index <= 155 && path.len() - index - 1 <= 100)
+            .map(|(index, _)| index)
+            .next()
+            .ok_or_else(|| format!("{} is too long for a tar header", entry.path))?;
+        (&path[..split], &path[split + 1..])
+    };
+    if entry.size > MAX_SIZE {
+        return Err(format!("{} is too large for a tar archive", entry.path));
+    }
+    let seconds = entry.modified.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+
+    header[0..name.len()].copy_from_slice(name);
+    header[100..108].copy_from_slice(format!("{:07o}\0", entry.mode & 0o7777).as_bytes());
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{:011o}\0", entry.size).as_bytes());
+    header[136..148].copy_from_slice(format!("{:011o}\0", seconds.min(MAX_SIZE)).as_bytes());
+    header[156] = match entry.kind {
+        EntryKind::Dir => b'5',
+        _ => b'0',
+    };
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix);
+    let (sum, _) = checksums(&header);
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+    Ok(header)
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+struct Builder<'a, W: Write, F: FnMut(&TarEntry)> {
+    out: W,
+    archive: &'a str,
+    // The archive and the temp file it is built in, so archiving the directory they are in does
+    // not include them
+    skipped: Vec<PathBuf>,
+    on_entry: F,
+    count: usize,
+}
+
+impl<W: Write, F: FnMut(&TarEntry)> Builder<'_, W, F> {
+    fn add(&mut self, path: &Path, name: String) -> Result<(), AppError> {
+        let shown = path.to_string_lossy();
+        let metadata = fs::symlink_metadata(path).map_err(|e| AppError::io("read", shown.as_ref(), e))?;
+        if fs::canonicalize(path).is_ok_and(|real| self.skipped.contains(&real)) {
+            return Ok(());
+        }
+        let kind = if metadata.is_dir() {
+            EntryKind::Dir
+        } else if metadata.is_file() {
+            EntryKind::File
+        } else {
+            // Like search, symlinks and special files are left out
+            return Ok(());
+        };
+        let entry = TarEntry {
+            path: if kind == EntryKind::Dir { format!("{}/", name) } else { name },
+            kind,
+            size: if kind == EntryKind::File { metadata.len() } else { 0 },
+            mode: mode_of(&metadata),
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+            link: String::new(),
+        };
+        let header = header(&entry).map_err(|message| AppError::io("add", shown.as_ref(), invalid(message)))?;
+        self.out.write_all(&header).map_err(|e| AppError::io("write to", self.archive, e))?;
+
+        if kind == EntryKind::File {
+            let file = File::open(path).map_err(|e| AppError::io("open", shown.as_ref(), e))?;
+            let copied = io::copy(&mut file.take(entry.size), &mut self.out)
+                .map_err(|e| AppError::io("write to", self.archive, e))?;
+            if copied < entry.size {
+                // The size is in the header already, so a file that shrank meanwhile is an error
+                let e = invalid(String::from("the file shrank while it was being archived"));
+                return Err(AppError::io("add", shown.as_ref(), e));
+            }
+            let padding = (BLOCK - (entry.size % BLOCK as u64) as usize) % BLOCK;
+            self.out
+                .write_all(&[0; BLOCK][..padding])
+                .map_err(|e| AppError::io("write to", self.archive, e))?;
+        }
+        (self.on_entry)(&entry);
+        self.count += 1;
+
+        if kind == EntryKind::Dir {
+            let mut children: Vec<_> = fs::read_dir(path)
+                .map_err(|e| AppError::io("read directory", shown.as_ref(), e))?
+                .filter_map(Result::ok)
+                .map(|child| child.file_name())
+                .collect();
+            // Sorted, so the same tree always gives the same archive
+            children.sort();
+            for child in children {
+                let child_name = format!("{}{}", entry.path, child.to_string_lossy());
+                self.add(&path.join(&child), child_name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Archives files and directories (with everything below them) into `archive`. Entries are named
+// from the last component of each path on, so `create_tar("a.tar", &["/home/me/notes"])` stores
+// notes/... Each added entry is reported to `on_entry`. Returns the number of entries.
+pub fn create_tar<F>(archive: &str, paths: &[&str], mode: WriteMode, on_entry: F) -> Result<usize, AppError>
+where
+    F: FnMut(&TarEntry),
+{
+    // Built in a temp file and renamed into place, so a failed run leaves an existing archive
+    // alone and never leaves half of one behind
+    write_atomic_with(archive, mode, false, |file, temp_path| {
+        let mut skipped = Vec::new();
+        skipped.extend(fs::canonicalize(temp_path).ok());
+        skipped.extend(fs::canonicalize(resolve(archive)?).ok());
+        let mut builder = Builder { out: BufWriter::new(file), archive, skipped, on_entry, count: 0 };
+        add_all(&mut builder, paths)
+    })
+}
+
+fn add_all<W: Write, F: FnMut(&TarEntry)>(builder: &mut Builder<W, F>, paths: &[&str]) -> Result<usize, AppError> {
+    let archive = builder.archive;
+    for path in paths {
+        let resolved = PathBuf::from(resolve(path)?);
+        let name = match fs::canonicalize(&resolved).ok().as_deref().and_then(Path::file_name) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => {
+                let e = invalid(String::from("a path with a name is needed, not the filesystem root"));
+                return Err(AppError::io("add", *path, e));
+            }
+        };
+        builder.add(&resolved, name)?;
+    }
+    // Two zero blocks end the archive
+    builder
+        .out
+        .write_all(&[0; BLOCK * 2])
+        .and_then(|_| builder.out.flush())
+        .map_err(|e| AppError::io("write to", archive, e))?;
+    Ok(builder.count)
+}
+
+// Only plain relative paths are extracted, an entry named /etc/passwd or ../x is refused
+fn entry_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if path.as_os_str().is_empty() { None } else { Some(path) }
+}
+
+// Unpacks `archive` into `dest`, which is created if needed. Existing files are only replaced
+// with `overwrite`. Links and special entries are reported to `on_entry` but skipped.
+// Returns the number of files and directories extracted.
+pub fn extract_tar<F>(archive: &str, dest: &str, overwrite: bool, mut on_entry: F) -> Result<usize, AppError>
+where
+    F: FnMut(&TarEntry),
+{
+    let mut reader = open_archive(archive)?;
+    let dest_dir = PathBuf::from(resolve(dest)?);
+    fs::create_dir_all(&dest_dir).map_err(|e| AppError::io("create directory", dest, e))?;
+
+    let mut count = 0;
+    while let Some(entry) = reader.next_entry().map_err(|e| AppError::io("read", archive, e))? {
+        on_entry(&entry);
+        if entry.kind != EntryKind::File && entry.kind != EntryKind::Dir {
+            continue;
+        }
+        let relative = entry_path(&entry.path).ok_or_else(|| {
+            AppError::io("extract", archive, invalid(format!("unsafe path {} in the archive", entry.path)))
+        })?;
+        // Resolved again per entry, so a symlink already in `dest` can not lead out of the root
+        let joined = dest_dir.join(&relative);
+        let shown = joined.to_string_lossy().into_owned();
+        let target = PathBuf::from(resolve(&shown)?);
+
+        let existing = fs::symlink_metadata(&target).ok();
+        if entry.kind == EntryKind::Dir {
+            match existing {
+                Some(metadata) if metadata.is_dir() => {}
+                Some(_) => {
+                    let e = io::Error::new(io::ErrorKind::AlreadyExists, "a file with that name exists");
+                    return Err(AppError::io("create directory", shown, e));
+                }
+                None => fs::create_dir_all(&target).map_err(|e| AppError::io("create directory", &shown, e))?,
+            }
+            count += 1;
+            continue;
+        }
+
+        match existing {
+            Some(metadata) if metadata.is_dir() => {
+                let e = io::Error::new(io::ErrorKind::IsADirectory, "a directory with that name exists");
+                return Err(AppError::io("extract to", shown, e));
+            }
+            Some(_) if !overwrite => {
+                let e = io::Error::new(io::ErrorKind::AlreadyExists, "the file already exists");
+                return Err(AppError::io("extract to", shown, e));
+            }
+            // Removed rather than truncated, so a symlink there is replaced instead of written through
+            Some(_) => fs::remove_file(&target).map_err(|e| AppError::io("replace", &shown, e))?,
+            None => {}
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| AppError::io("create directory", parent.to_string_lossy(), e))?;
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&target)
+            .map_err(|e| AppError::io("create", &shown, e))?;
+        reader.copy_data(&mut file).map_err(|e| AppError::io("extract", &shown, e))?;
+        set_mode(&file, entry.mode).map_err(|e| AppError::io("set permissions of", &shown, e))?;
+        file.set_modified(entry.modified).map_err(|e| AppError::io("set the time of", &shown, e))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(unix)]
+fn set_mode(file: &File, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    // setuid and friends are dropped, an archive should not hand those out
+    file.set_permissions(fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &File, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fileio::test_dir;
+
+    fn entry(path: &str, kind: EntryKind, size: u64) -> TarEntry {
+        TarEntry { path: path.to_string(), kind, size, mode: 0o644, modified: UNIX_EPOCH, link: String::new() }
+    }
+
+    #[test]
+    fn creates_lists_and_extracts() {
+        let dir = test_dir("tar-roundtrip");
+        let long = "a-rather-long-directory-name-that-goes-on-for-a-while/and-another-one-below-it-as-well";
+        fs::create_dir_all(dir.join("docs/empty")).unwrap();
+        fs::create_dir_all(dir.join("docs").join(long)).unwrap();
+        fs::write(dir.join("docs/notes.txt"), "some notes\n").unwrap();
+        fs::write(dir.join("docs").join(long).join("deep.bin"), vec![7u8; 1500]).unwrap();
+
+        let archive = dir.join("docs.tar");
+        let archive = archive.to_str().unwrap();
+        let docs = dir.join("docs");
+        let count = create_tar(archive, &[docs.to_str().unwrap()], WriteMode::Create, |_| {}).unwrap();
+        assert_eq!(count, 6);
+        assert_eq!(fs::metadata(archive).unwrap().len() % BLOCK as u64, 0);
+
+        let mut listed = Vec::new();
+        list_tar(archive, |entry| {
+            listed.push((entry.path.clone(), entry.kind, entry.size));
+            true
+        })
+        .unwrap();
+        let deep = format!("docs/{}/deep.bin", long);
+        assert!(listed.contains(&(String::from("docs/empty/"), EntryKind::Dir, 0)));
+        assert!(listed.contains(&(deep.clone(), EntryKind::File, 1500)));
+        assert_eq!(listed[0].0, "docs/");
+
+        let out = dir.join("out");
+        assert_eq!(extract_tar(archive, out.to_str().unwrap(), false, |_| {}).unwrap(), 6);
+        assert_eq!(fs::read_to_string(out.join("docs/notes.txt")).unwrap(), "some notes\n");
+        assert_eq!(fs::read(out.join(&deep)).unwrap(), vec![7u8; 1500]);
+        assert!(out.join("docs/empty").is_dir());
+        // A second time the files exist already
+        assert!(extract_tar(archive, out.to_str().unwrap(), false, |_| {}).is_err());
+        assert!(extract_tar(archive, out.to_str().unwrap(), true, |_| {}).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_old_archive_when_overwriting_fails() {
+        let dir = test_dir("tar-overwrite");
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        let archive = dir.join("a.tar");
+        let archive = archive.to_str().unwrap();
+        let a = dir.join("a.txt");
+        create_tar(archive, &[a.to_str().unwrap()], WriteMode::Create, |_| {}).unwrap();
+        let old = fs::read(archive).unwrap();
+
+        let missing = dir.join("missing");
+        assert!(create_tar(archive, &[missing.to_str().unwrap()], WriteMode::Overwrite, |_| {}).is_err());
+        assert_eq!(fs::read(archive).unwrap(), old);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Archiving the directory the archive is in leaves out both the old archive and the new one
+        let mut names = Vec::new();
+        create_tar(archive, &[dir.to_str().unwrap()], WriteMode::Overwrite, |entry| names.push(entry.path.clone())).unwrap();
+        let top = dir.file_name().unwrap().to_string_lossy();
+        assert_eq!(names, [format!("{}/", top), format!("{}/a.txt", top)]);
+        assert_ne!(fs::read(archive).unwrap(), old);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_checksums_and_unsafe_paths() {
+        let dir = test_dir("tar-invalid");
+        let mut bytes = header(&entry("../evil.txt", EntryKind::File, 4)).unwrap().to_vec();
+        bytes.extend_from_slice(b"evil");
+        bytes.resize(BLOCK * 4, 0);
+        let archive = dir.join("evil.tar");
+        fs::write(&archive, &bytes).unwrap();
+        let archive = archive.to_str().unwrap();
+
+        let error = extract_tar(archive, dir.join("out").to_str().unwrap(), false, |_| {}).unwrap_err();
+        assert!(format!("{:?}", error).contains("unsafe path ../evil.txt"), "{:?}", error);
+        assert!(!dir.join("evil.txt").exists());
+
+        bytes[0] = b'X';
+        fs::write(archive, &bytes).unwrap();
+        let error = list_tar(archive, |_| true).unwrap_err();
+        assert!(format!("{:?}", error).contains("checksum mismatch at byte 0"), "{:?}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn splits_long_names_into_the_prefix() {
+        let name = format!("{}/{}", "d".repeat(120), "f".repeat(90));
+        let block = header(&entry(&name, EntryKind::File, 0)).unwrap();
+        assert_eq!(text_field(&block[345..500]), "d".repeat(120));
+        assert_eq!(text_field(&block[0..100]), "f".repeat(90));
+        assert!(header(&entry(&"x".repeat(101), EntryKind::File, 0)).is_err());
+        assert_eq!(parse_number(b"0000644\0"), Some(0o644));
+        assert_eq!(parse_number(&[0x80, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0]), Some(1 << 33));
+    }
+}
@@ -0,0 +1,272 @@
+use std::cmp::Ordering;
+use std::io;
+use regex::{Regex, RegexBuilder};
+use crate::apperror::AppError;
+use super::{convert_line_endings, read_decoded, Encoding, LineEnding};
+
+const STEPS: &str = "sort, uniq, reverse, upper, lower, trim, replace or number";
+
+#[derive(Clone, Debug)]
+pub enum Transform {
+    // sort [-r] [-n]: descending with -r, by the leading number with -n
+    Sort { reverse: bool, numeric: bool },
+    // Drops a line equal to the one before it, like uniq(1), so usually after sort
+    Uniq,
+    // Last line first
+    Reverse,
+    Upper,
+    Lower,
+    // Whitespace at both ends of every line
+    Trim,
+    // replace /regex/replacement/[i], any punctuation works as the delimiter
+    Replace { pattern: Regex, replacement: String },
+    // Line numbers in front, like cat -n
+    Number,
+}
+
+// Steps separated by '|', applied left to right to the lines of a file
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    steps: Vec<Transform>,
+}
+
+// The number a line starts with for sort -n, 0 for lines that do not start with one
+fn leading_number(line: &str) -> f64 {
+    let line = line.trim_start();
+    let end = line
+        .char_indices()
+        .find(|&(index, c)| !(c.is_ascii_digit() || c == '.' || (index == 0 && (c == '-' || c == '+'))))
+        .map_or(line.len(), |(index, _)| index);
+    // The longest prefix that parses, so "1.2.3" sorts as 1.2
+    (1..=end).rev().find_map(|len| line[..len].parse().ok()).unwrap_or(0.0)
+}
+
+impl Transform {
+    fn apply(&self, mut lines: Vec<String>) -> Vec<String> {
+        match self {
+            Transform::Sort { reverse, numeric } => {
+                let compare = |a: &String, b: &String| -> Ordering {
+                    if *numeric {
+                        leading_number(a).total_cmp(&leading_number(b)).then_with(|| a.cmp(b))
+                    } else {
+                        a.cmp(b)
+                    }
+                };
+                if *reverse {
+                    lines.sort_by(|a, b| compare(b, a));
+                } else {
+                    lines.sort_by(compare);
+                }
+            }
+            Transform::Uniq => lines.dedup(),
+            Transform::Reverse => lines.reverse(),
+            Transform::Upper => lines.iter_mut().for_each(|line| *line = line.to_uppercase()),
+            Transform::Lower => lines.iter_mut().for_each(|line| *line = line.to_lowercase()),
+            Transform::Trim => lines.iter_mut().for_each(|line| *line = line.trim().to_string()),
+            Transform::Replace { pattern, replacement } => lines
+                .iter_mut()
+                .for_each(|line| *line = pattern.replace_all(line, replacement.as_str()).into_owned()),
+            Transform::Number => {
+                for (index, line) in lines.iter_mut().enumerate() {
+                    *line = format!("{:>6}\t{}", index + 1, line);
+                }
+            }
+        }
+        lines
+    }
+}
+
+// Reads a delimited part of a replace step. An escaped delimiter stands for itself, every other
+// backslash is kept for the regex.
+fn delimited(chars: &[char], position: &mut usize, delimiter: char) -> Result<String, String> {
+    let mut part = String::new();
+    while let Some(&c) = chars.get(*position) {
+        *position += 1;
+        match c {
+            '\\' if chars.get(*position) == Some(&delimiter) => {
+                part.push(delimiter);
+                *position += 1;
+            }
+            '\\' => {
+                part.push('\\');
+                if let Some(&next) = chars.get(*position) {
+                    part.push(next);
+                    *position += 1;
+                }
+            }
+            c if c == delimiter => return Ok(part),
+            c => part.push(c),
+        }
+    }
+    Err(format!("replace is missing its closing '{}'", delimiter))
+}
+
+impl Pipeline {
+    // Parses e.g. "sort | uniq | upper" or "trim | replace /a|b/x/i | number"
+    pub fn parse(text: &str) -> Result<Pipeline, AppError> {
+        Pipeline::parse_steps(text).map_err(|message| AppError::invalid("pipeline", message))
+    }
+
+    fn parse_steps(text: &str) -> Result<Pipeline, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut position = 0;
+        let skip_spaces = |position: &mut usize| {
+            while chars.get(*position).is_some_and(|c| c.is_whitespace()) {
+                *position += 1;
+            }
+        };
+
+        let mut steps = Vec::new();
+        loop {
+            skip_spaces(&mut position);
+            let start = position;
+            while chars.get(position).is_some_and(|c| c.is_ascii_alphabetic()) {
+                position += 1;
+            }
+            let name: String = chars[start..position].iter().collect();
+            let step = match name.to_ascii_lowercase().as_str() {
+                "" if steps.is_empty() && position == chars.len() => return Err(String::from("the pipeline is empty")),
+                "" => return Err(format!("expected one of {}", STEPS)),
+                "sort" => {
+                    let (mut reverse, mut numeric) = (false, false);
+                    loop {
+                        skip_spaces(&mut position);
+                        if chars.get(position) != Some(&'-') {
+                            break;
+                        }
+                        position += 1;
+                        while let Some(flag) = chars.get(position).filter(|c| c.is_ascii_alphabetic()) {
+                            match flag {
+                                'r' => reverse = true,
+                                'n' => numeric = true,
+                                other => return Err(format!("sort has no option -{}, only -r and -n", other)),
+                            }
+                            position += 1;
+                        }
+                    }
+                    Transform::Sort { reverse, numeric }
+                }
+                "uniq" => Transform::Uniq,
+                "reverse" => Transform::Reverse,
+                "upper" => Transform::Upper,
+                "lower" => Transform::Lower,
+                "trim" => Transform::Trim,
+                "number" => Transform::Number,
+                "replace" => {
+                    skip_spaces(&mut position);
+                    let delimiter = match chars.get(position) {
+                        Some(&c) if !c.is_alphanumeric() && !c.is_whitespace() && c != '\\' => c,
+                        _ => return Err(String::from("replace needs a pattern like /regex/replacement/")),
+                    };
+                    position += 1;
+                    let pattern = delimited(&chars, &mut position, delimiter)?;
+                    let replacement = delimited(&chars, &mut position, delimiter)?;
+                    let mut ignore_case = false;
+                    while let Some(flag) = chars.get(position).filter(|c| c.is_ascii_alphabetic()) {
+                        match flag {
+                            'i' => ignore_case = true,
+                            // Every match on a line is replaced anyway, g is accepted out of habit
+                            'g' => {}
+                            other => return Err(format!("replace has no flag '{}', only i", other)),
+                        }
+                        position += 1;
+                    }
+                    let pattern = RegexBuilder::new(&pattern)
+                        .case_insensitive(ignore_case)
+                        .build()
+                        .map_err(|e| format!("invalid pattern: {}", e))?;
+                    Transform::Replace { pattern, replacement }
+                }
+                other => return Err(format!("unknown step '{}', expected one of {}", other, STEPS)),
+            };
+            steps.push(step);
+
+            skip_spaces(&mut position);
+            match chars.get(position) {
+                None => return Ok(Pipeline { steps }),
+                Some('|') => position += 1,
+                Some(other) => return Err(format!("expected '|' after {}, found '{}'", name, other)),
+            }
+        }
+    }
+
+    pub fn apply(&self, lines: Vec<String>) -> Vec<String> {
+        self.steps.iter().fold(lines, |lines, step| step.apply(lines))
+    }
+
+    // Runs over the lines of `text`. Its line endings, and a final line break, are kept.
+    pub fn apply_to_text(&self, text: &str) -> String {
+        let lines = self.apply(text.lines().map(str::to_string).collect());
+        let mut result = lines.join("\n");
+        if text.ends_with('\n') && !lines.is_empty() {
+            result.push('\n');
+        }
+        match LineEnding::detect(text) {
+            Some(LineEnding::CrLf) => convert_line_endings(&result, LineEnding::CrLf),
+            _ => result,
+        }
+    }
+}
+
+// The file's contents after the pipeline, with the file's encoding to write them back in. Any
+// encoding read_decoded detects works, binary files are refused.
+pub fn transform_file(path: &str, pipeline: &Pipeline) -> Result<(String, Encoding), AppError> {
+    match read_decoded(path)? {
+        (text, Some(encoding)) => Ok((pipeline.apply_to_text(&text), encoding)),
+        (_, None) => {
+            let e = io::Error::new(io::ErrorKind::InvalidData, "the file looks binary, not text");
+            Err(AppError::io("transform", path, e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(pipeline: &str, text: &str) -> String {
+        Pipeline::parse(pipeline).unwrap().apply_to_text(text)
+    }
+
+    #[test]
+    fn applies_steps_in_order() {
+        let text = "pear\napple\npear\n Fig \n";
+        assert_eq!(run("sort | uniq | upper", text), " FIG \nAPPLE\nPEAR\n");
+        assert_eq!(run("trim|sort -r|number", text), "     1\tpear\n     2\tpear\n     3\tapple\n     4\tFig\n");
+        assert_eq!(run("reverse | lower | trim", "A\r\n B\r\n"), "b\r\na\r\n");
+        assert_eq!(run("sort -n", "10 x\n9 y\n-1 z\nnone\n"), "-1 z\nnone\n9 y\n10 x\n");
+    }
+
+    #[test]
+    fn replaces_with_regexes() {
+        // The '|' inside the pattern is alternation, not the next step
+        assert_eq!(run("replace /cat|dog/pet/ | upper", "cat and dog\n"), "PET AND PET\n");
+        assert_eq!(run(r"replace #(\w+)@(\w+)#$2 at $1#i", "me@home"), "home at me");
+        assert_eq!(run(r"replace /a\/b/c/", "a/b"), "c");
+        assert_eq!(run("replace /X/y/i", "xX"), "yy");
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let error = |text: &str| Pipeline::parse_steps(text).unwrap_err();
+        assert_eq!(error(""), "the pipeline is empty");
+        assert_eq!(error("sort | shuffle"), format!("unknown step 'shuffle', expected one of {}", STEPS));
+        assert_eq!(error("sort uniq"), "expected '|' after sort, found 'u'");
+        assert_eq!(error("sort |"), format!("expected one of {}", STEPS));
+        assert_eq!(error("replace /a/b"), "replace is missing its closing '/'");
+        assert!(error("replace /(/x/").starts_with("invalid pattern"));
+    }
+
+    #[test]
+    fn transforms_files_in_any_encoding() {
+        let dir = crate::fileio::test_dir("transform-latin1");
+        let path = dir.join("words.txt");
+        std::fs::write(&path, b"\xE9t\xE9\nabc\n").unwrap();
+        let pipeline = Pipeline::parse("upper").unwrap();
+        let (text, encoding) = transform_file(path.to_str().unwrap(), &pipeline).unwrap();
+        assert_eq!((text.as_str(), encoding), ("\u{c9}T\u{c9}\nABC\n", Encoding::Latin1));
+        std::fs::write(&path, [0u8, 1, 2, 3]).unwrap();
+        assert!(transform_file(path.to_str().unwrap(), &pipeline).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use crate::apperror::AppError;
+use super::{for_each_line, format_time, InvalidUtf8, LineSelection};
+
+// Lines shown when tailing starts, like tail -f
+const TAIL_LINES: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchMode {
+    // Print the whole file again after every change
+    Reprint,
+    // Print only the lines appended since the last look
+    Tail,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchOptions {
+    pub mode: WatchMode,
+    // How often size and modification time are checked
+    pub interval: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            mode: WatchMode::Reprint,
+            interval: Duration::from_millis(500),
+        }
+    }
+}
+
+// What a poll looks at. Comparing these is all the change detection there is, no inotify needed.
+type Snapshot = Option<(u64, Option<SystemTime>)>;
+
+fn snapshot(path: &str) -> Result<Snapshot, AppError> {
+    match fs::metadata(super::resolve(path)?) {
+        Ok(metadata) => Ok(Some((metadata.len(), metadata.modified().ok()))),
+        // A file that is replaced by rename or recreated is briefly missing, keep waiting for it
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(AppError::io("watch", path, e)),
+    }
+}
+
+// Shows `path` and keeps showing its changes through `on_line` until `should_stop` returns true,
+// which is asked once per poll, or `on_line` returns false. The file has to exist at the start.
+pub fn watch<F, S>(path: &str, options: WatchOptions, mut on_line: F, mut should_stop: S) -> Result<(), AppError>
+where
+    F: FnMut(&str) -> bool,
+    S: FnMut() -> bool,
+{
+    let mut last = snapshot(path)?;
+    let mut tail = Tail { offset: 0, partial: Vec::new() };
+    let size = match last {
+        Some((size, _)) => size,
+        None => return Err(AppError::io("watch", path, io::ErrorKind::NotFound.into())),
+    };
+
+    let mut stopped = false;
+    match options.mode {
+        WatchMode::Reprint => reprint(path, &mut on_line, &mut stopped)?,
+        WatchMode::Tail => {
+            for_each_line(path, LineSelection::Tail(TAIL_LINES), InvalidUtf8::Lossy, |_, line| {
+                stopped = !on_line(line);
+                !stopped
+            })?;
+            tail.offset = size;
+        }
+    }
+
+    while !stopped && !should_stop() {
+        thread::sleep(options.interval);
+        let current = snapshot(path)?;
+        if current == last {
+            continue;
+        }
+
+        match (last, current) {
+            (_, None) => stopped = !on_line(&format!("--- {} was removed, waiting for it to come back ---", path)),
+            (None, Some(_)) => {
+                stopped = !on_line(&format!("--- {} was created again ---", path));
+                tail.restart();
+                if !stopped && options.mode == WatchMode::Reprint {
+                    reprint(path, &mut on_line, &mut stopped)?;
+                }
+            }
+            (Some(_), Some((size, modified))) => match options.mode {
+                WatchMode::Reprint => {
+                    let when = modified.map_or(String::from("-"), format_time);
+                    stopped = !on_line(&format!("--- {} changed at {} UTC ---", path, when));
+                    if !stopped {
+                        reprint(path, &mut on_line, &mut stopped)?;
+                    }
+                }
+                WatchMode::Tail if size < tail.offset => {
+                    stopped = !on_line(&format!("--- {} was truncated ---", path));
+                    tail.restart();
+                }
+                WatchMode::Tail => {}
+            },
+        }
+        if !stopped && options.mode == WatchMode::Tail && current.is_some() {
+            stopped = !tail.read_appended(path, &mut on_line)?;
+        }
+        last = current;
+    }
+    Ok(())
+}
+
+fn reprint<F>(path: &str, on_line: &mut F, stopped: &mut bool) -> Result<(), AppError>
+where
+    F: FnMut(&str) -> bool,
+{
+    for_each_line(path, LineSelection::All, InvalidUtf8::Lossy, |_, line| {
+        *stopped = !on_line(line);
+        !*stopped
+    })
+}
+
+// Where tailing got to, and the start of a line whose end has not been written yet
+struct Tail {
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl Tail {
+    fn restart(&mut self) {
+        self.offset = 0;
+        self.partial.clear();
+    }
+
+    // Reports the complete lines written since the last call, returns false once on_line says stop
+    fn read_appended<F>(&mut self, path: &str, on_line: &mut F) -> Result<bool, AppError>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let mut file = File::open(super::resolve(path)?).map_err(|e| AppError::io("open", path, e))?;
+        file.seek(SeekFrom::Start(self.offset))
+            .map_err(|e| AppError::io("seek in", path, e))?;
+        let read = file
+            .read_to_end(&mut self.partial)
+            .map_err(|e| AppError::io("read", path, e))?;
+        self.offset += read as u64;
+
+        let complete = match self.partial.iter().rposition(|byte| *byte == b'\n') {
+            Some(index) => index + 1,
+            None => return Ok(true),
+        };
+        let lines: Vec<u8> = self.partial.drain(..complete).collect();
+        for line in String::from_utf8_lossy(&lines).lines() {
+            if !on_line(line) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use crate::fileio::test_dir;
+
+    fn append(path: &str, text: &str) {
+        OpenOptions::new().append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn tails_appended_lines() {
+        let dir = test_dir("watch-tail");
+        let path = dir.join("app.log");
+        let path = path.to_str().unwrap();
+        fs::write(path, "old 1\nold 2\n").unwrap();
+
+        let options = WatchOptions { mode: WatchMode::Tail, interval: Duration::from_millis(1) };
+        let mut lines = Vec::new();
+        let mut polls = 0;
+        watch(path, options, |line| {
+            lines.push(line.to_string());
+            true
+        }, || {
+            polls += 1;
+            match polls {
+                2 => append(path, "new 1\nhalf"),
+                3 => append(path, " done\n"),
+                4 => fs::write(path, "fresh\n").unwrap(),
+                _ => {}
+            }
+            polls > 5
+        })
+        .unwrap();
+
+        assert_eq!(lines, ["old 1", "old 2", "new 1", "half done", format!("--- {} was truncated ---", path).as_str(), "fresh"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reprints_on_change() {
+        let dir = test_dir("watch-reprint");
+        let path = dir.join("notes.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "one\n").unwrap();
+
+        let options = WatchOptions { mode: WatchMode::Reprint, interval: Duration::from_millis(1) };
+        let mut lines = Vec::new();
+        let mut polls = 0;
+        watch(path, options, |line| {
+            lines.push(line.to_string());
+            true
+        }, || {
+            polls += 1;
+            if polls == 2 {
+                fs::write(path, "one\ntwo\n").unwrap();
+            }
+            polls > 3
+        })
+        .unwrap();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "one");
+        assert!(lines[1].starts_with(&format!("--- {} changed at ", path)));
+        assert_eq!(&lines[2..], ["one", "two"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,8 @@
 use std::io;
-use std::io::Write;
 use rand::Rng;
-use crate::errorlog::log_error;
+use log::error;
+use crate::apperror::AppError;
+use crate::flush_stdout;
 
 pub fn guess(){
     let mut count = 0;
@@ -9,17 +10,21 @@ pub fn guess(){
     let number_first = secret_number/10;
     let number_second = secret_number%10;
     println!("Guess the number: ");
-    io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+    flush_stdout();
     loop {
         let mut guess = String::new();
-        io::stdin().read_line(&mut guess).expect("Failed to read line");
-        io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+        if let Err(e) = io::stdin().read_line(&mut guess) {
+            error!("{:?}", AppError::io("read from", "stdin", e));
+            break;
+        }
+        flush_stdout();
 
         let guess: u32 = match guess.trim().parse() {
             Ok(num) => num,
-            Err(_) => {
+            Err(e) => {
+                println!("{}", AppError::parse(guess.trim(), e));
                 println!("Please enter a valid number: ");
-                io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+                flush_stdout();
 
                 continue;
             }
@@ -28,20 +33,20 @@ pub fn guess(){
         let guess_second = guess%10;
         if guess == secret_number {
             println!("You guessed the number in {} attempts!", count);
-            io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+            flush_stdout();
 
             break;
         }
         else if guess_first == number_first || guess_second == number_second {
             println!("You are close to the number: ");
-            io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+            flush_stdout();
             count += 1;
 
         }
         else {
             println!("Try again: ");
-            io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+            flush_stdout();
             count += 1;
         }
     }
-}
\ No newline at end of file
+}
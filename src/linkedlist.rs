@@ -10,9 +10,8 @@ use std::rc::Rc;
 
 
 use std::cell::RefCell;
-use std::io;
-use std::io::Write;
-use crate::read_input;
+use crate::apperror::{AppError, ListError};
+use crate::{flush_stdout, read_input};
 //RefCell<T> is a type that provides interior mutability,
 //meaning you can mutate the data inside even if the RefCell itself is immutable.
 //This is achieved by enforcing Rust's borrowing rules at runtime rather than at compile time.
@@ -40,7 +39,7 @@ struct LinkedList<T> {
     tail: Option<Rc<RefCell<Node<T>>>>,
 }
 
-impl<T: std::fmt::Debug + PartialEq + Clone> LinkedList<T> {
+impl<T: std::fmt::Debug + std::fmt::Display + PartialEq + Clone> LinkedList<T> {
     fn new() -> Self {
         LinkedList {
             head: None,
@@ -70,36 +69,36 @@ impl<T: std::fmt::Debug + PartialEq + Clone> LinkedList<T> {
     }
 
 
-    fn find_index(&self, value: &T) -> Option<usize> {
+    fn find_index(&self, value: &T) -> Result<usize, AppError> {
         let mut current = self.head.clone();
         let mut index = 0;
 
         while let Some(node) = current {
             if node.borrow().value == *value {
-                return Some(index);
+                return Ok(index);
             }
             index += 1;
             current = node.borrow().next.clone();
         }
-        None
+        Err(ListError::ValueNotFound(value.to_string()).into())
     }
 
 
-    fn find_value(&self, index: usize) -> Option<T> {
+    fn find_value(&self, index: usize) -> Result<T, AppError> {
         let mut current = self.head.clone();
         let mut i = 0;
 
         while let Some(node) = current {
             if i == index {
-                return Some(node.borrow().value.clone());
+                return Ok(node.borrow().value.clone());
             }
             i += 1;
             current = node.borrow().next.clone();
         }
-        None
+        Err(ListError::IndexOutOfBounds(index).into())
     }
 
-    fn delete_val(&mut self, value: T) {
+    fn delete_val(&mut self, value: T) -> Result<(), AppError> {
         let mut current = self.head.clone();
 
         while let Some(node) = current {
@@ -127,14 +126,15 @@ impl<T: std::fmt::Debug + PartialEq + Clone> LinkedList<T> {
                         self.tail = None;
                     }
                 }
-                return;
+                return Ok(());
             }
             current = node.borrow().next.clone();
         }
+        Err(ListError::ValueNotFound(value.to_string()).into())
     }
 
 
-    fn delete_index(&mut self, index: usize) {
+    fn delete_index(&mut self, index: usize) -> Result<(), AppError> {
         let mut current = self.head.clone();
         let mut i = 0;
 
@@ -161,14 +161,15 @@ impl<T: std::fmt::Debug + PartialEq + Clone> LinkedList<T> {
                         self.tail = None;
                     }
                 }
-                return;
+                return Ok(());
             }
             i += 1;
             current = node.borrow().next.clone();
         }
+        Err(ListError::IndexOutOfBounds(index).into())
     }
 
-    fn append_after_index(&mut self, index: usize, value: T) {
+    fn append_after_index(&mut self, index: usize, value: T) -> Result<(), AppError> {
         let mut current = self.head.clone();
         let mut i = 0;
 
@@ -186,15 +187,16 @@ impl<T: std::fmt::Debug + PartialEq + Clone> LinkedList<T> {
                 } else {
                     self.tail = Some(new_node.clone());
                 }
-                return;
+                return Ok(());
             }
             i += 1;
             current = node.borrow().next.clone();
         }
+        Err(ListError::IndexOutOfBounds(index).into())
     }
 
 
-    fn append_before_index(&mut self, index: usize, value: T) {
+    fn append_before_index(&mut self, index: usize, value: T) -> Result<(), AppError> {
         let mut current = self.head.clone();
         let mut i = 0;
 
@@ -212,11 +214,12 @@ impl<T: std::fmt::Debug + PartialEq + Clone> LinkedList<T> {
                 } else {
                     self.head = Some(new_node.clone());
                 }
-                return;
+                return Ok(());
             }
             i += 1;
             current = node.borrow().next.clone();
         }
+        Err(ListError::IndexOutOfBounds(index).into())
     }
 
     fn print_list(&self){
@@ -245,85 +248,104 @@ pub(crate) fn linked_list_input(){
         3. Delete index\n4. Find index\n5. Find value\
         \n6. Append after index\n7. Append before index\n\
         8. Print list\n-1. Exit");
-        option = read_input().trim().parse().unwrap();
+        let input = read_input();
+        option = match input.trim().parse() {
+            Ok(option) => option,
+            Err(e) => {
+                println!("{}", AppError::parse(input.trim(), e));
+                continue;
+            }
+        };
         match option{
             1 => {
                 print!("Enter value to append: ");
-                io::stdout().flush().unwrap();
+                flush_stdout();
                 let value = read_input();
                 list.append(value);
             }
             2 => {
                 print!("Enter value to delete: ");
-                io::stdout().flush().unwrap();
+                flush_stdout();
                 let value = read_input();
-                list.delete_val(value);
+                if let Err(e) = list.delete_val(value) {
+                    println!("{}", e);
+                }
             }
             3 => {
                 print!("Enter index to delete: ");
-                io::stdout().flush().unwrap();
-                let index = match read_input().trim().parse::<usize>() {
+                flush_stdout();
+                let input = read_input();
+                let index = match input.trim().parse::<usize>() {
                     Ok(idx) => idx,
-                    Err(_) => {
-                        println!("Invalid input. Please enter a valid index.");
+                    Err(e) => {
+                        println!("{}", AppError::parse(input.trim(), e));
                         continue;
                     }
                 };
-                list.delete_index(index);
+                if let Err(e) = list.delete_index(index) {
+                    println!("{}", e);
+                }
             }
             4 => {
                 print!("Enter value to find index: ");
-                io::stdout().flush().unwrap();
+                flush_stdout();
                 let value = read_input();
                 match list.find_index(&value) {
-                    Some(index) => println!("Index of {} is {}", value, index),
-                    None => println!("Value not found in list"),
+                    Ok(index) => println!("Index of {} is {}", value, index),
+                    Err(e) => println!("{}", e),
                 }
             }
             5 => {
                 print!("Enter index to find value: ");
-                io::stdout().flush().unwrap();
-                let index = match read_input().trim().parse::<usize>() {
+                flush_stdout();
+                let input = read_input();
+                let index = match input.trim().parse::<usize>() {
                     Ok(idx) => idx,
-                    Err(_) => {
-                        println!("Invalid input. Please enter a valid index.");
+                    Err(e) => {
+                        println!("{}", AppError::parse(input.trim(), e));
                         continue;
                     }
                 };
                 match list.find_value(index) {
-                    Some(value) => println!("Value at index {} is {}", index, value),
-                    None => println!("Index out of bounds"),
+                    Ok(value) => println!("Value at index {} is {}", index, value),
+                    Err(e) => println!("{}", e),
                 }
             }
             6=>{
                 print!("Enter index to append after: ");
-                io::stdout().flush().unwrap();
-                let index = match read_input().trim().parse::<usize>() {
+                flush_stdout();
+                let input = read_input();
+                let index = match input.trim().parse::<usize>() {
                     Ok(idx) => idx,
-                    Err(_) => {
-                        println!("Invalid input. Please enter a valid index.");
+                    Err(e) => {
+                        println!("{}", AppError::parse(input.trim(), e));
                         continue;
                     }
                 };
                 print!("Enter value to append: ");
-                io::stdout().flush().unwrap();
+                flush_stdout();
                 let value = read_input();
-                list.append_after_index(index, value);
+                if let Err(e) = list.append_after_index(index, value) {
+                    println!("{}", e);
+                }
             }
             7=>{
                 print!("Enter index to append before: ");
-                io::stdout().flush().unwrap();
-                let index = match read_input().trim().parse::<usize>() {
+                flush_stdout();
+                let input = read_input();
+                let index = match input.trim().parse::<usize>() {
                     Ok(idx) => idx,
-                    Err(_) => {
-                        println!("Invalid input. Please enter a valid index.");
+                    Err(e) => {
+                        println!("{}", AppError::parse(input.trim(), e));
                         continue;
                     }
                 };
                 print!("Enter value to append: ");
-                io::stdout().flush().unwrap();
+                flush_stdout();
                 let value = read_input();
-                list.append_before_index(index, value);
+                if let Err(e) = list.append_before_index(index, value) {
+                    println!("{}", e);
+                }
             }
             8 => {
                 list.print_list();
@@ -336,4 +358,17 @@ pub(crate) fn linked_list_input(){
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_missing_values_as_typed() {
+        let mut list = LinkedList::new();
+        list.append(String::from("x"));
+        assert_eq!(list.find_index(&String::from("x")).unwrap(), 0);
+        assert_eq!(list.find_index(&String::from("y")).unwrap_err().to_string(), "Value y not found in list");
+        assert_eq!(list.delete_val(String::from("a \"b\"")).unwrap_err().to_string(), "Value a \"b\" not found in list");
+    }
+}
@@ -1,3 +1,5 @@
+mod apperror;
+mod cli;
 mod helloworld;
 mod basiccalculator;
 mod guessnumber;
@@ -6,20 +8,54 @@ mod menu;
 mod errorlog;
 mod linkedlist;
 
+use std::env;
 use std::io::{self, Write};
+use std::process::ExitCode;
 use menu::get_menu_options;
-use crate::errorlog::log_error;
+use log::{error, warn};
+use apperror::{AppError, CalcError};
 
 fn read_input() -> String{
     let mut input = String::new();
     io::stdin().read_line(&mut input)
-        .unwrap_or_else(|_| {
-            log_error("Failed to read line");
+        .unwrap_or_else(|e| {
+            error!("{:?}", AppError::io("read from", "stdin", e));
             0
         });
     input.trim().to_string()
 }
-fn main() {
+fn flush_stdout() {
+    io::stdout().flush().unwrap_or_else(|e| warn!("{:?}", AppError::io("flush", "stdout", e)));
+}
+fn read_number() -> Result<i32, AppError> {
+    let input = read_input();
+    input.parse().map_err(|e| AppError::parse(input, e))
+}
+fn main() -> ExitCode {
+    errorlog::init();
+
+    // Same as --root on the command line, checked before anything else so a bad root stops here
+    if let Ok(root) = env::var("UNDERPASS_ROOT") {
+        if let Err(e) = fileio::set_root(&root) {
+            errorlog::use_stderr();
+            error!("{:?}", e);
+            errorlog::shutdown();
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        errorlog::use_stderr();
+        let code = cli::run(&args);
+        errorlog::shutdown();
+        return code;
+    }
+
+    if let Some(root) = fileio::root() {
+        println!("File paths are limited to {}", root.display());
+    }
+
     //Holy shit working with castings and flushes are hard!
     loop{
         let menu_options = get_menu_options();
@@ -27,9 +63,7 @@ fn main() {
             println!("{} - {}", option.number, option.description);
         }
         print!("Select code snippet: ");
-        // unwrap_or_else unwraps the result if Ok, executes the parantheses if Err
-        // |_| ignores the error and instead calls my function
-        io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+        flush_stdout();
 
 
         let selection_input = read_input();
@@ -37,7 +71,7 @@ fn main() {
             Ok(num) => num,
             Err(_) => {
                 println!("Please enter a valid number");
-                io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+                flush_stdout();
                 continue;
             }
         };
@@ -48,7 +82,7 @@ fn main() {
             4=>{
                 loop {
                     print!("Enter operation (+, -, *, /, %, ^, !, q to quit): ");
-                    io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
+                    flush_stdout();
 
 
                     let input = read_input();
@@ -57,7 +91,10 @@ fn main() {
                         continue;
                     }
 
-                    let operation = input.chars().next().unwrap();
+                    let operation = match input.chars().next() {
+                        Some(operation) => operation,
+                        None => continue,
+                    };
 
                     if operation == 'q' {
                         break;
@@ -68,34 +105,52 @@ fn main() {
 
                     if operation != '!' {
                         print!("Enter first number: ");
-                        io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
-                        a = read_input().parse().expect("Please enter a valid number");
+                        flush_stdout();
+                        a = match read_number() {
+                            Ok(num) => num,
+                            Err(e) => {
+                                println!("{}", e);
+                                continue;
+                            }
+                        };
 
                         print!("Enter second number: ");
-                        io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
-                        b = read_input().parse().expect("Please enter a valid number");
+                        flush_stdout();
+                        b = match read_number() {
+                            Ok(num) => num,
+                            Err(e) => {
+                                println!("{}", e);
+                                continue;
+                            }
+                        };
                     } else {
                         print!("Enter number: ");
-                        io::stdout().flush().unwrap_or_else(|_| log_error("Failed to flush stdout"));
-                        a = read_input().parse().expect("Please enter a valid number");
+                        flush_stdout();
+                        a = match read_number() {
+                            Ok(num) => num,
+                            Err(e) => {
+                                println!("{}", e);
+                                continue;
+                            }
+                        };
                         b = 0; // b is not used in factorial
                     }
 
-                    let result:f32 = match operation {
-                        '+' => basiccalculator::add(a, b) as f32,
-                        '-' => basiccalculator::subtract(a, b) as f32,
-                        '*' => basiccalculator::multiply(a, b) as f32,
+                    let result: Result<f32, AppError> = match operation {
+                        '+' => basiccalculator::add(a, b).map(|r| r as f32),
+                        '-' => basiccalculator::subtract(a, b).map(|r| r as f32),
+                        '*' => basiccalculator::multiply(a, b).map(|r| r as f32),
                         '/' => basiccalculator::divide(a, b),
-                        '%' => basiccalculator::modulo(a, b) as f32,
-                        '^' => basiccalculator::power(a, b) as f32,
-                        '!' => basiccalculator::factorial(a) as f32,
-                        _ => {
-                            println!("Invalid operation");
-                            continue;
-                        }
+                        '%' => basiccalculator::modulo(a, b).map(|r| r as f32),
+                        '^' => basiccalculator::power(a, b).map(|r| r as f32),
+                        '!' => basiccalculator::factorial(a).map(|r| r as f32),
+                        _ => Err(CalcError::UnknownOperation(operation).into()),
                     };
 
-                    println!("Result: {}", result);
+                    match result {
+                        Ok(result) => println!("Result: {}", result),
+                        Err(e) => println!("{}", e),
+                    }
                 }
             }
             5=>{
@@ -103,7 +158,11 @@ fn main() {
                 println!("{}", greeting);}
             6=>{fileio::append_to_file();}
             7=>{linkedlist::linked_list_input();}
-            8=>{break;}
+            8=>{
+                errorlog::shutdown();
+                return ExitCode::SUCCESS;
+            }
+            9=>{fileio::file_tools();}
             _ => {println!("Invalid selection. Please enter a valid selection.");}
         }
     }
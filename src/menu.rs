@@ -12,6 +12,35 @@ pub fn get_menu_options() -> Vec<MenuOption>{
         MenuOption{number: 5, description: "Hello world"},
         MenuOption{number: 6, description: "Append to file"},
         MenuOption{number: 7, description: "Linked List"},
-        MenuOption{number: 8, description: "Exit"}
+        MenuOption{number: 8, description: "Exit"},
+        MenuOption{number: 9, description: "File tools"}
     ]
-}
\ No newline at end of file
+}
+
+pub fn get_file_menu_options() -> Vec<MenuOption>{
+    vec![
+        MenuOption{number: 1, description: "Edit file"},
+        MenuOption{number: 2, description: "Search files"},
+        MenuOption{number: 3, description: "Compare files"},
+        MenuOption{number: 4, description: "Checksums"},
+        MenuOption{number: 5, description: "Query CSV"},
+        MenuOption{number: 6, description: "Watch file"},
+        MenuOption{number: 7, description: "Manage files"},
+        MenuOption{number: 8, description: "Tar archives"},
+        MenuOption{number: 9, description: "Text statistics"},
+        MenuOption{number: 10, description: "Transform text"},
+        MenuOption{number: 11, description: "Back"}
+    ]
+}
+
+pub fn get_manage_menu_options() -> Vec<MenuOption>{
+    vec![
+        MenuOption{number: 1, description: "Copy"},
+        MenuOption{number: 2, description: "Move or rename"},
+        MenuOption{number: 3, description: "Delete (to trash)"},
+        MenuOption{number: 4, description: "Restore from trash"},
+        MenuOption{number: 5, description: "Empty trash"},
+        MenuOption{number: 6, description: "New directory"},
+        MenuOption{number: 7, description: "Back"}
+    ]
+}